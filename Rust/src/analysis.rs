@@ -0,0 +1,171 @@
+use crate::map::*;
+use crate::pathfinding::*;
+use crate::simulation::*;
+use crate::solver::*;
+use crate::util::*;
+use std::collections::BTreeMap;
+use std::fmt;
+
+// Difficulty report for a board
+
+pub struct ChoiceReport {
+    pub position: Coord,
+    pub branches: Vec<(Direction, Option<usize>)>, // Steps to the next line through each branch
+}
+
+pub struct BoardReport {
+    pub name: String,
+    pub tiles: usize,
+    pub lap_length: Option<usize>,
+    pub mandatory_turns: Option<usize>,
+    pub chikanes: usize,
+    pub blue: usize,
+    pub choices: Vec<ChoiceReport>,
+    pub crash_risk: Vec<(u8, Vec<(Coord, f64)>)>, // Riskiest hexes for each gear
+    pub lap_times: Vec<(String, Option<f64>)>,
+}
+
+type Distances = BTreeMap<(Coord, Direction), usize>;
+
+// Shortest distance through the line crossings of `from` to the line of `field`,
+// and the fewest turns needed when driving that distance
+fn leg(map: &HexMap, field: &DistanceField, from: &[(Coord, Vec<Direction>)]) -> Option<(usize, usize)> {
+    let distances = &field.distances;
+    let mut turns_memo = BTreeMap::new();
+    map.line_exits(from)
+        .into_iter()
+        .filter_map(|(c, d)| {
+            let dist = field.distance(c, d)?;
+            let turns = [Turn::Straight, Turn::Left, Turn::Right]
+                .iter()
+                .filter(|t| distances.get(&(c, d + **t)) == Some(&(dist - 1)))
+                .map(|t| min_turns(distances, c, d + *t, &mut turns_memo) + if *t == Turn::Straight { 0 } else { 1 })
+                .min()?;
+            Some((dist, turns))
+        })
+        .min()
+}
+
+// Fewest turns on a shortest path when leaving `c` in direction `d`
+fn min_turns(distances: &Distances, c: Coord, d: Direction, memo: &mut BTreeMap<(Coord, Direction), usize>) -> usize {
+    if let Some(turns) = memo.get(&(c, d)) {
+        return *turns;
+    }
+    let bredth = distances[&(c, d)];
+    let turns = if bredth == 0 {
+        0
+    } else {
+        let next = c + d.to_coord();
+        [Turn::Straight, Turn::Left, Turn::Right]
+            .iter()
+            .filter(|t| distances.get(&(next, d + **t)) == Some(&(bredth - 1)))
+            .map(|t| min_turns(distances, next, d + *t, memo) + if *t == Turn::Straight { 0 } else { 1 })
+            .min()
+            .unwrap_or(0)
+    };
+    memo.insert((c, d), turns);
+    turns
+}
+
+impl BoardReport {
+    pub fn new(name: &str, map: &HexMap, games: usize) -> Self {
+        let graph = map.graph();
+        let goal_field = DistanceField::new(&graph, &map.tiles, &map.start_line, &Obstacles::default());
+        let mid_field = DistanceField::new(&graph, &map.tiles, &map.mid_line, &Obstacles::default());
+
+        let first = leg(map, &mid_field, &map.start_line);
+        let second = leg(map, &goal_field, &map.mid_line);
+        let (goal, mid) = (goal_field.distances, mid_field.distances);
+        let (lap_length, mandatory_turns) = match (first, second) {
+            (Some((d1, t1)), Some((d2, t2))) => (Some(d1 + d2), Some(t1 + t2)),
+            _ => (None, None),
+        };
+
+        let choices = map
+            .tiles
+            .iter()
+            .filter(|(_, t)| t.choice)
+            .map(|(c, t)| {
+                // Measure towards the closest line
+                let closest = |distances: &Distances| t.directions.iter().filter_map(|d| distances.get(&(*c, *d))).min().copied();
+                let distances = match (closest(&mid), closest(&goal)) {
+                    (Some(m), Some(g)) if g < m => &goal,
+                    (None, Some(_)) => &goal,
+                    _ => &mid,
+                };
+                ChoiceReport {
+                    position: *c,
+                    branches: t.directions.iter().map(|d| (*d, distances.get(&(*c, *d)).map(|b| b + 1))).collect(),
+                }
+            })
+            .collect();
+
+        use crate::map::{PlayerGearStrategy as PGS, PlayerStepStrategy as PSS};
+        let mut lap_times = vec![];
+
+        let mut best = Simulation::new(map.clone(), vec![(PGS::Best, PSS::Best)]);
+        let best_stats = best.run(games, 0);
+        lap_times.push(("Best".to_string(), best_stats.average_lap()));
+
+        let mut optimal = Simulation::new(map.clone(), vec![(PGS::Optimal, PSS::Optimal)]);
+        optimal.solver = Some(std::sync::Arc::new(Solver::solve(optimal.map.clone())));
+        lap_times.push(("Optimal".to_string(), optimal.run(games, 0).average_lap()));
+        lap_times.push(("Optimal (exact)".to_string(), Some(optimal.solver.as_ref().unwrap().lap_value())));
+
+        let crash_risk = (1..=3)
+            .map(|gear| (gear, best_stats.crash_risk(gear, games / 20 + 1).into_iter().take(3).collect()))
+            .collect();
+
+        BoardReport {
+            name: name.to_string(),
+            tiles: map.tiles.len(),
+            lap_length,
+            mandatory_turns,
+            chikanes: map.tiles.values().filter(|t| t.chikane).count(),
+            blue: map.tiles.values().filter(|t| t.blue).count(),
+            choices,
+            crash_risk,
+            lap_times,
+        }
+    }
+}
+
+fn or_unknown<T: fmt::Display>(value: &Option<T>) -> String {
+    value.as_ref().map_or("-".to_string(), |v| v.to_string())
+}
+
+impl fmt::Display for BoardReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "== {} ==", self.name)?;
+        writeln!(f, "Tiles:           {}", self.tiles)?;
+        writeln!(f, "Lap length:      {}", or_unknown(&self.lap_length))?;
+        writeln!(f, "Mandatory turns: {}", or_unknown(&self.mandatory_turns))?;
+        writeln!(f, "Chikanes:        {}", self.chikanes)?;
+        writeln!(f, "Blue tiles:      {}", self.blue)?;
+
+        writeln!(f, "Choice tiles:    {}", self.choices.len())?;
+        for choice in &self.choices {
+            let branches: Vec<String> = choice
+                .branches
+                .iter()
+                .map(|(d, length)| format!("{:?} {}", d, or_unknown(length)))
+                .collect();
+            writeln!(f, "  ({}, {}): {}", choice.position.q, choice.position.r, branches.join(", "))?;
+        }
+
+        writeln!(f, "Fall-off risk per gear:")?;
+        for (gear, hexes) in &self.crash_risk {
+            let hexes: Vec<String> = hexes
+                .iter()
+                .map(|(c, risk)| format!("({}, {}) {:.0}%", c.q, c.r, risk * 100.0))
+                .collect();
+            writeln!(f, "  Gear {}: {}", gear, if hexes.is_empty() { "-".to_string() } else { hexes.join(", ") })?;
+        }
+
+        writeln!(f, "Average lap (turns):")?;
+        for (strategy, turns) in &self.lap_times {
+            writeln!(f, "  {:<16} {}", strategy, turns.map_or("-".to_string(), |t| format!("{:.2}", t)))?;
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,53 @@
+use crate::generator::*;
+use crate::importer::*;
 use crate::map::*;
 use crate::util::{*, Direction::*};
 use std::collections::BTreeMap;
 use sdl2::pixels::Color;
 
+/// Name of a built in board and the function building it
+pub type Board = (&'static str, fn() -> HexMap);
+
+pub const BOARDS: [Board; 4] = [
+    ("rtfm", rtfm),
+    ("random_direction", random_direction),
+    ("hourglass_loop", hourglass_loop),
+    ("hyper_chikane", hyper_chikane),
+];
+
+/// Built in board, a generated one: `generated:<seed>[,<option>=<value>...]`,
+/// or an imported one: `<maps.py>:<function>` or `<image.png>:<scale>,<x>,<y>`
+pub fn board(name: &str) -> Option<HexMap> {
+    if let Some(b) = BOARDS.iter().find(|(n, _)| *n == name) {
+        return Some(b.1());
+    }
+    let result = match name.strip_prefix("generated:") {
+        Some(spec) => TrackConfig::parse(spec).and_then(|config| generate(&config)).map(Some),
+        None => import_board(name),
+    };
+    match result {
+        Ok(board) => board,
+        Err(e) => {
+            eprintln!("Could not load {}: {}", name, e);
+            None
+        }
+    }
+}
+
+/// Colors the built in boards give their players
+pub fn player_colors() -> Vec<Color> {
+    vec![
+        Color::RGB(255, 0, 0),
+        Color::RGB(0, 0, 255),
+        Color::RGB(0, 255, 0),
+        Color::RGB(255, 255, 0),
+        Color::RGB(255, 0, 255),
+        Color::RGB(0, 255, 255),
+        Color::RGB(255, 100, 100),
+        Color::RGB(100, 100, 255),
+    ]
+}
+
 pub fn rtfm() -> HexMap {
     HexMap {
         tiles: BTreeMap::from([
@@ -113,7 +158,6 @@ pub fn rtfm() -> HexMap {
     }
 }
 
-// TODO: Choice fields are no longer guaranteed to have a previous round, as players can skip the choice in an earlier round! (Choice should be a map from round?)
 pub fn random_direction() -> HexMap {
     HexMap {
         tiles: BTreeMap::from([
@@ -302,7 +346,6 @@ pub fn random_direction() -> HexMap {
     }
 }
 
-// TODO: Choice fields are no longer guaranteed to have a previous round, as players can skip the choice in an earlier round! (Choice should be a map from round?)
 pub fn hourglass_loop() -> HexMap {
     HexMap {
         tiles: BTreeMap::from([
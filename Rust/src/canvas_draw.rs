@@ -1,12 +1,60 @@
 use sdl2::pixels::Color;
 use sdl2::rect::Point;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
+use sdl2::render::{Canvas, RenderTarget};
 
 use crate::Direction;
 
+// Anything the board can be drawn on, the window or an exported image
+pub trait Painter: Sized {
+    fn set_draw_color(&mut self, color: Color);
+    fn draw_line<P1: Into<Point>, P2: Into<Point>>(&mut self, start: P1, end: P2) -> Result<(), String>;
+    fn draw_lines(&mut self, points: &[Point]) -> Result<(), String>;
+
+    fn fill_hexagon(&mut self, x: i32, y: i32, scale: f64) {
+        for i in 0..6 * (scale as i32) {
+            draw_hexagon(self, x + i % 2, y, scale - (i as f64) / 6.0);
+        }
+        // let (xs,ys) : (Vec<i32>, Vec<i32>) =
+        //     (0..=6)
+        //     .map(|a| (a as f64) * std::f64::consts::PI / 180.0 * 60.0)
+        //     .map(|angle: f64|
+        //          (x + (scale * angle.cos()) as i32,
+        //           y + (scale * angle.sin()) as i32)// .into()
+        //     ).unzip();
+        // let _ = <Canvas<Window> as DrawRenderer>::filled_polygon(xs, ys, Color::RGB(255, 255, 0));
+    }
+}
+
+impl<T: RenderTarget> Painter for Canvas<T> {
+    fn set_draw_color(&mut self, color: Color) {
+        Canvas::set_draw_color(self, color);
+    }
+
+    fn draw_line<P1: Into<Point>, P2: Into<Point>>(&mut self, start: P1, end: P2) -> Result<(), String> {
+        Canvas::draw_line(self, start, end)
+    }
+
+    fn draw_lines(&mut self, points: &[Point]) -> Result<(), String> {
+        Canvas::draw_lines(self, points)
+    }
+}
+
+// Corners of a hexagon, also used for filled polygons in exports
+pub fn hexagon_points(x: i32, y: i32, scale: f64) -> Vec<Point> {
+    (0..=6)
+        .map(|a| (a as f64) * std::f64::consts::PI / 180.0 * 60.0)
+        .map(|angle: f64| {
+            (
+                x + (scale * angle.cos()) as i32,
+                y + (scale * angle.sin()) as i32,
+            )
+                .into()
+        })
+        .collect()
+}
+
 pub fn draw_hexagon_side_checkerboard(
-    canvas: &mut Canvas<Window>,
+    canvas: &mut impl Painter,
     x: i32,
     y: i32,
     scale: f64,
@@ -50,7 +98,7 @@ pub fn draw_hexagon_side_checkerboard(
     }
 }
 
-pub fn draw_hexagon_side(canvas: &mut Canvas<Window>, x: i32, y: i32, scale: f64, d: Direction) {
+pub fn draw_hexagon_side(canvas: &mut impl Painter, x: i32, y: i32, scale: f64, d: Direction) {
     let a = match d {
         Direction::UR => 0,
         Direction::U => 1,
@@ -72,30 +120,87 @@ pub fn draw_hexagon_side(canvas: &mut Canvas<Window>, x: i32, y: i32, scale: f64
     let _ = canvas.draw_lines(&points[..]);
 }
 
-pub fn draw_hexagon(canvas: &mut Canvas<Window>, x: i32, y: i32, scale: f64) {
-    let points: Vec<Point> = (0..=6)
-        .map(|a| (a as f64) * std::f64::consts::PI / 180.0 * 60.0)
-        .map(|angle: f64| {
-            (
-                x + (scale * angle.cos()) as i32,
-                y + (scale * angle.sin()) as i32,
-            )
-                .into()
-        })
-        .collect();
-    let _ = canvas.draw_lines(&points[..]);
+pub fn draw_hexagon(canvas: &mut impl Painter, x: i32, y: i32, scale: f64) {
+    let _ = canvas.draw_lines(&hexagon_points(x, y, scale)[..]);
+}
+
+// Stroke font on a 4x6 grid, for captions and labels in exports
+fn glyph(c: char) -> &'static [&'static [(i32, i32)]] {
+    match c.to_ascii_uppercase() {
+        '0' => &[&[(0, 0), (4, 0), (4, 6), (0, 6), (0, 0)], &[(0, 6), (4, 0)]],
+        '1' => &[&[(1, 1), (2, 0), (2, 6)], &[(1, 6), (3, 6)]],
+        '2' => &[&[(0, 0), (4, 0), (4, 3), (0, 3), (0, 6), (4, 6)]],
+        '3' => &[&[(0, 0), (4, 0), (4, 6), (0, 6)], &[(0, 3), (4, 3)]],
+        '4' => &[&[(0, 0), (0, 3), (4, 3)], &[(4, 0), (4, 6)]],
+        '5' | 'S' => &[&[(4, 0), (0, 0), (0, 3), (4, 3), (4, 6), (0, 6)]],
+        '6' => &[&[(4, 0), (0, 0), (0, 6), (4, 6), (4, 3), (0, 3)]],
+        '7' => &[&[(0, 0), (4, 0), (2, 6)]],
+        '8' => &[&[(0, 0), (4, 0), (4, 6), (0, 6), (0, 0)], &[(0, 3), (4, 3)]],
+        '9' => &[&[(4, 3), (0, 3), (0, 0), (4, 0), (4, 6), (0, 6)]],
+        'A' => &[&[(0, 6), (0, 2), (2, 0), (4, 2), (4, 6)], &[(0, 3), (4, 3)]],
+        'B' => &[&[(0, 0), (3, 0), (4, 1), (4, 2), (3, 3), (0, 3)], &[(3, 3), (4, 4), (4, 5), (3, 6), (0, 6), (0, 0)]],
+        'C' => &[&[(4, 0), (0, 0), (0, 6), (4, 6)]],
+        'D' => &[&[(0, 0), (3, 0), (4, 1), (4, 5), (3, 6), (0, 6), (0, 0)]],
+        'E' => &[&[(4, 0), (0, 0), (0, 6), (4, 6)], &[(0, 3), (3, 3)]],
+        'F' => &[&[(4, 0), (0, 0), (0, 6)], &[(0, 3), (3, 3)]],
+        'G' => &[&[(4, 0), (0, 0), (0, 6), (4, 6), (4, 3), (2, 3)]],
+        'H' => &[&[(0, 0), (0, 6)], &[(4, 0), (4, 6)], &[(0, 3), (4, 3)]],
+        'I' => &[&[(1, 0), (3, 0)], &[(2, 0), (2, 6)], &[(1, 6), (3, 6)]],
+        'J' => &[&[(4, 0), (4, 6), (0, 6), (0, 4)]],
+        'K' => &[&[(0, 0), (0, 6)], &[(4, 0), (0, 3), (4, 6)]],
+        'L' => &[&[(0, 0), (0, 6), (4, 6)]],
+        'M' => &[&[(0, 6), (0, 0), (2, 3), (4, 0), (4, 6)]],
+        'N' => &[&[(0, 6), (0, 0), (4, 6), (4, 0)]],
+        'O' => &[&[(0, 0), (4, 0), (4, 6), (0, 6), (0, 0)]],
+        'P' => &[&[(0, 6), (0, 0), (4, 0), (4, 3), (0, 3)]],
+        'Q' => &[&[(0, 0), (4, 0), (4, 6), (0, 6), (0, 0)], &[(2, 4), (4, 6)]],
+        'R' => &[&[(0, 6), (0, 0), (4, 0), (4, 3), (0, 3), (4, 6)]],
+        'T' => &[&[(0, 0), (4, 0)], &[(2, 0), (2, 6)]],
+        'U' => &[&[(0, 0), (0, 6), (4, 6), (4, 0)]],
+        'V' => &[&[(0, 0), (2, 6), (4, 0)]],
+        'W' => &[&[(0, 0), (1, 6), (2, 3), (3, 6), (4, 0)]],
+        'X' => &[&[(0, 0), (4, 6)], &[(4, 0), (0, 6)]],
+        'Y' => &[&[(0, 0), (2, 3), (4, 0)], &[(2, 3), (2, 6)]],
+        'Z' => &[&[(0, 0), (4, 0), (0, 6), (4, 6)]],
+        '+' => &[&[(0, 3), (4, 3)], &[(2, 1), (2, 5)]],
+        '-' => &[&[(0, 3), (4, 3)]],
+        '=' => &[&[(0, 2), (4, 2)], &[(0, 4), (4, 4)]],
+        ':' => &[&[(2, 1), (2, 2)], &[(2, 4), (2, 5)]],
+        '.' => &[&[(2, 5), (2, 6)]],
+        ',' => &[&[(2, 5), (1, 7)]],
+        '(' => &[&[(3, 0), (1, 2), (1, 4), (3, 6)]],
+        ')' => &[&[(1, 0), (3, 2), (3, 4), (1, 6)]],
+        '/' => &[&[(0, 6), (4, 0)]],
+        '<' => &[&[(4, 0), (0, 3), (4, 6)]],
+        '>' => &[&[(0, 0), (4, 3), (0, 6)]],
+        '^' => &[&[(0, 2), (2, 0), (4, 2)]],
+        '_' => &[&[(0, 6), (4, 6)]],
+        _ => &[],
+    }
+}
+
+/// Width in pixels of `text` drawn with `draw_text`
+pub fn text_width(text: &str, size: f64) -> i32 {
+    (text.chars().count() as f64 * size * 0.75) as i32
 }
 
-pub fn fill_hexagon(canvas: &mut Canvas<Window>, x: i32, y: i32, scale: f64) {
-    for i in 0..6 * (scale as i32) {
-        draw_hexagon(canvas, x + i % 2, y, scale - (i as f64) / 6.0);
+// Text with its top left corner at (x, y), `size` is the height of a line
+pub fn draw_text(canvas: &mut impl Painter, text: &str, x: i32, y: i32, size: f64) {
+    let unit = size / 8.0;
+    for (i, c) in text.chars().enumerate() {
+        let left = x as f64 + i as f64 * size * 0.75;
+        for stroke in glyph(c) {
+            let points: Vec<Point> = stroke
+                .iter()
+                .map(|(gx, gy)| {
+                    (
+                        (left + unit * (*gx as f64 + 1.0)) as i32,
+                        (y as f64 + unit * (*gy as f64 + 1.0)) as i32,
+                    )
+                        .into()
+                })
+                .collect();
+            let _ = canvas.draw_lines(&points[..]);
+        }
     }
-    // let (xs,ys) : (Vec<i32>, Vec<i32>) =
-    //     (0..=6)
-    //     .map(|a| (a as f64) * std::f64::consts::PI / 180.0 * 60.0)
-    //     .map(|angle: f64|
-    //          (x + (scale * angle.cos()) as i32,
-    //           y + (scale * angle.sin()) as i32)// .into()
-    //     ).unzip();
-    // let _ = <Canvas<Window> as DrawRenderer>::filled_polygon(xs, ys, Color::RGB(255, 255, 0));
 }
@@ -0,0 +1,186 @@
+use crate::player::*;
+use crate::trace;
+use crate::util::*;
+use std::collections::BTreeMap;
+
+// Chikanes from `Python/rules.tex`: a chikane has room for one car. A car
+// ending its turn alone on one turns over and blocks it. A car that would land
+// on or drive through the blocked chikane stops on the field before it, from
+// behind or from the side alike, bonks the turned over car and turns it back,
+// which lifts the block. Two cars on a field block it without any collision.
+// Chikanes with forced directions, like the ones in `rtfm`, need nothing
+// more: blocking only looks at the field, whichever way it is entered.
+
+/// What happened on a chikane at the end of a turn
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChikaneEvent {
+    TurnedOver { player: usize, position: Coord },
+    Bonked { player: usize, turned_back: Vec<usize>, position: Coord }, // Stopped before the chikane at `position`
+    Stopped { player: usize, position: Coord }, // Stopped before a chikane full without anyone turned over
+    Shared { player: usize, position: Coord },  // Landed next to a car turned back, nothing blocks
+}
+
+/// Chikanes blocked by a car turned over on them
+pub fn blocked(tiles: &BTreeMap<Coord, Tile>, players: &[Player]) -> Vec<Coord> {
+    players
+        .iter()
+        .filter(|p| p.turned_over && tiles.get(&p.position).is_some_and(|t| t.chikane))
+        .map(|p| p.position)
+        .collect()
+}
+
+/// A bonk only counts for the turn it happened in
+pub fn start_turn(player: &mut Player) {
+    player.bonked = false;
+}
+
+/// Settle the chikanes once the turn of `player` is over
+pub fn end_turn(tiles: &BTreeMap<Coord, Tile>, players: &mut [Player], player: usize) -> Vec<ChikaneEvent> {
+    let mut events = vec![];
+    let position = players[player].position;
+
+    // Blocked on the way, the car stopped right before the field it drove towards
+    if players[player].bonked {
+        let ahead = position + players[player].direction.to_coord();
+        if tiles.get(&ahead).is_some_and(|t| t.chikane) {
+            let turned_back: Vec<usize> = (0..players.len())
+                .filter(|i| players[*i].position == ahead && players[*i].turned_over)
+                .collect();
+            for i in &turned_back {
+                players[*i].turned_over = false;
+            }
+            events.push(if turned_back.is_empty() {
+                ChikaneEvent::Stopped { player, position: ahead }
+            } else {
+                ChikaneEvent::Bonked { player, turned_back, position: ahead }
+            });
+        }
+    }
+
+    if tiles.get(&position).is_some_and(|t| t.chikane) {
+        let alone = players.iter().filter(|p| p.position == position).count() == 1;
+        if alone {
+            players[player].turned_over = true;
+            events.push(ChikaneEvent::TurnedOver { player, position });
+        } else {
+            events.push(ChikaneEvent::Shared { player, position });
+        }
+    }
+
+    for event in &events {
+        trace!("{:?}", event);
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boards::*;
+    use crate::map::*;
+
+    fn car(position: Coord, direction: Direction) -> Player {
+        PlayerBuilder::new(1, player_colors(), vec![(position, direction)]).next_player()
+    }
+
+    // A chikane at the origin with plain road around it
+    fn tiles() -> BTreeMap<Coord, Tile> {
+        let chikane = Coord::new(0, 0);
+        let mut tiles = BTreeMap::from([(chikane, Tile::new(vec![Direction::U]).chikane())]);
+        for d in (0..6).map(Direction::from_num) {
+            tiles.insert(chikane + d.to_coord(), Tile::new(vec![Direction::U]));
+        }
+        tiles
+    }
+
+    // Car `player` stopped in front of the chikane driving in `direction`
+    fn bonk(tiles: &BTreeMap<Coord, Tile>, chikane: Coord, direction: Direction) -> (Vec<Player>, Vec<ChikaneEvent>) {
+        let mut over = car(chikane, Direction::U);
+        over.turned_over = true;
+        let mut bonker = car(chikane - direction.to_coord(), direction);
+        bonker.bonked = true;
+
+        let mut players = vec![over, bonker];
+        assert_eq!(blocked(tiles, &players), vec![chikane]);
+        let events = end_turn(tiles, &mut players, 1);
+        (players, events)
+    }
+
+    #[test]
+    fn alone_turns_over() {
+        let tiles = tiles();
+        let chikane = Coord::new(0, 0);
+        let mut players = vec![car(chikane, Direction::U), car(chikane + Direction::D.to_coord(), Direction::U)];
+
+        let events = end_turn(&tiles, &mut players, 0);
+        assert_eq!(events, vec![ChikaneEvent::TurnedOver { player: 0, position: chikane }]);
+        assert!(players[0].turned_over);
+        assert_eq!(blocked(&tiles, &players), vec![chikane]);
+    }
+
+    #[test]
+    fn two_cars_share() {
+        let tiles = tiles();
+        let chikane = Coord::new(0, 0);
+        let mut players = vec![car(chikane, Direction::U), car(chikane, Direction::U)];
+
+        let events = end_turn(&tiles, &mut players, 1);
+        assert_eq!(events, vec![ChikaneEvent::Shared { player: 1, position: chikane }]);
+        assert!(!players[0].turned_over && !players[1].turned_over);
+        assert!(blocked(&tiles, &players).is_empty());
+    }
+
+    #[test]
+    fn bonk_from_behind() {
+        let tiles = tiles();
+        let chikane = Coord::new(0, 0);
+        let (players, events) = bonk(&tiles, chikane, Direction::U);
+
+        assert_eq!(events, vec![ChikaneEvent::Bonked { player: 1, turned_back: vec![0], position: chikane }]);
+        assert!(!players[0].turned_over);
+        assert!(blocked(&tiles, &players).is_empty());
+    }
+
+    #[test]
+    fn bonk_from_the_side() {
+        let tiles = tiles();
+        let chikane = Coord::new(0, 0);
+        let (players, events) = bonk(&tiles, chikane, Direction::UR);
+
+        assert_eq!(events, vec![ChikaneEvent::Bonked { player: 1, turned_back: vec![0], position: chikane }]);
+        assert!(blocked(&tiles, &players).is_empty());
+    }
+
+    #[test]
+    fn stopped_without_anyone_turned_over() {
+        let tiles = tiles();
+        let chikane = Coord::new(0, 0);
+        let mut bonker = car(chikane + Direction::D.to_coord(), Direction::U);
+        bonker.bonked = true;
+        let mut players = vec![car(chikane, Direction::U), car(chikane, Direction::U), bonker];
+
+        let events = end_turn(&tiles, &mut players, 2);
+        assert_eq!(events, vec![ChikaneEvent::Stopped { player: 2, position: chikane }]);
+        assert!(players.iter().all(|p| !p.turned_over));
+    }
+
+    #[test]
+    fn forced_direction() {
+        // The forced chikane after the fork in `rtfm`, entered from either branch
+        let tiles = rtfm().tiles;
+        let chikane = Coord::tri(4, -3, -4);
+        assert!(tiles[&chikane].chikane && !tiles[&chikane].forced.is_empty());
+
+        let mut players = vec![car(chikane, Direction::U)];
+        assert_eq!(
+            end_turn(&tiles, &mut players, 0),
+            vec![ChikaneEvent::TurnedOver { player: 0, position: chikane }]
+        );
+
+        for from in tiles[&chikane].forced.keys() {
+            let (players, events) = bonk(&tiles, chikane, Direction::from_coord(chikane - *from));
+            assert_eq!(events, vec![ChikaneEvent::Bonked { player: 1, turned_back: vec![0], position: chikane }]);
+            assert!(blocked(&tiles, &players).is_empty());
+        }
+    }
+}
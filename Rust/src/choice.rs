@@ -0,0 +1,194 @@
+use crate::canvas_draw::*;
+use crate::util::*;
+use sdl2::pixels::Color;
+use std::collections::BTreeMap;
+
+// Forks from `Python/rules.tex`: the first car to reach a choice tile in a lap
+// picks the branch, and every car in that lap follows it. Driving down the
+// other branch is a technical fault for every field driven on it, and from its
+// next turn the car drives the same fields back to the fork before it goes on
+// along the picked branch.
+
+/// A car down the branch not picked for the lap
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct WrongBranch {
+    pub fork: Coord,
+    pub picked: Direction, // Branch to take at the fork once back
+    pub trail: Vec<Coord>, // Fields driven down the wrong branch, the last one the car is on
+    pub reversing: bool,   // Driving back, from the turn after taking the wrong branch
+}
+
+/// Branches picked at choice tiles, kept for every lap of the race
+#[derive(Clone, Default, Debug)]
+pub struct ChoiceLocks {
+    pub picks: BTreeMap<(Coord, usize), Direction>, // Choice tile and lap
+}
+
+impl ChoiceLocks {
+    /// Branch to take at `position` in lap `round`, none until a car has picked one
+    pub fn get(&self, position: Coord, round: usize) -> Option<Direction> {
+        self.picks.get(&(position, round)).copied()
+    }
+
+    /// Pick `direction` at `position` for lap `round`, unless a car already did
+    pub fn lock(&mut self, position: Coord, round: usize, direction: Direction) -> bool {
+        if self.picks.contains_key(&(position, round)) {
+            return false;
+        }
+        self.picks.insert((position, round), direction);
+        true
+    }
+
+    /// Branches picked for lap `round`
+    pub fn round(&self, round: usize) -> BTreeMap<Coord, Direction> {
+        self.picks
+            .iter()
+            .filter(|((_, r), _)| *r == round)
+            .map(|((c, _), d)| (*c, *d))
+            .collect()
+    }
+
+    /// Mark the branches picked for lap `round` on the board
+    pub fn draw(&self, canvas: &mut impl Painter, round: usize, start: Coord, scale: f64) {
+        canvas.set_draw_color(Color::RGB(220, 40, 40));
+        for (c, d) in self.round(round) {
+            let c = start + c * (scale as i32);
+            let angle = d.angle();
+            let cx = c.x() + (scale / 2_f64.sqrt() * angle.cos()) as i32;
+            let cy = c.y() + (scale / 2_f64.sqrt() * angle.sin()) as i32;
+            for i in -2..=2 {
+                let _ = canvas.draw_line((c.x() + i, c.y()), (cx + i, cy));
+                let _ = canvas.draw_line((c.x(), c.y() + i), (cx, cy + i));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boards::*;
+    use crate::graph::*;
+    use crate::map::*;
+    use crate::player::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    const BRANCH: i32 = 8;
+
+    // Takes `branch` at the fork, straight on everywhere else
+    struct Towards {
+        fork: Coord,
+        branch: Direction,
+    }
+
+    impl StepStrategy for Towards {
+        fn step_strategy(&mut self, player: &Player, _: &Vec<Turn>, _: &Tile) -> Option<Turn> {
+            if player.position != self.fork {
+                return Some(Turn::Straight);
+            }
+            [Turn::Straight, Turn::Left, Turn::Right].into_iter().find(|t| player.direction + *t == self.branch)
+        }
+    }
+
+    struct Keep;
+
+    impl GearStrategy for Keep {
+        fn gear_strategy(&mut self, _: &Player) -> ChangeGear {
+            ChangeGear::Stay
+        }
+    }
+
+    // A fork at the origin entered going up, both branches running straight on
+    fn tiles() -> BTreeMap<Coord, Tile> {
+        let fork = Coord::new(0, 0);
+        let mut tiles = BTreeMap::from([
+            (fork - Direction::U.to_coord(), Tile::new(vec![Direction::U])),
+            (fork, Tile::new(vec![Direction::UL, Direction::UR]).choice()),
+        ]);
+        for d in [Direction::UL, Direction::UR] {
+            for i in 1..=BRANCH {
+                tiles.insert(fork + d.to_coord() * i, Tile::new(vec![d]));
+            }
+        }
+        tiles
+    }
+
+    // Move `car` the fields of its roll as `GameState::step_game` does
+    fn drive(car: &mut Player, graph: &TrackGraph, locks: &ChoiceLocks, strategy: &mut Towards) {
+        while !car.finished {
+            if let Some(turns) = car.pre_step(graph, &vec![], locks) {
+                car.step(&turns, graph, strategy);
+            }
+        }
+    }
+
+    // Car in front of the fork with `roll`, the branch up and left picked for the lap
+    fn setup(roll: u8) -> (Player, TrackGraph, ChoiceLocks, Towards) {
+        let tiles = tiles();
+        let fork = Coord::new(0, 0);
+        let mut car = PlayerBuilder::new(1, player_colors(), vec![(fork - Direction::U.to_coord(), Direction::U)]).next_player();
+        car.face(Direction::U);
+        car.roll = vec![roll];
+        car.stalled = false;
+
+        let mut locks = ChoiceLocks::default();
+        locks.lock(fork, 1, Direction::UL);
+        let wrong = Towards {
+            fork,
+            branch: Direction::UR,
+        };
+        (car, TrackGraph::new(&tiles), locks, wrong)
+    }
+
+    #[test]
+    fn fault_per_field() {
+        let fork = Coord::new(0, 0);
+        for roll in 2..=6 {
+            let (mut car, graph, locks, mut wrong) = setup(roll);
+            drive(&mut car, &graph, &locks, &mut wrong);
+
+            let driven = roll as i32 - 1; // The first field is the fork
+            assert_eq!(car.position, fork + Direction::UR.to_coord() * driven);
+            assert_eq!(car.sips.faults, driven as u32);
+            assert_eq!(car.wrong_branch.as_ref().map(|w| w.trail.len()), Some(driven as usize));
+        }
+    }
+
+    #[test]
+    fn drives_back_to_fork() {
+        let fork = Coord::new(0, 0);
+        let (mut car, graph, locks, mut wrong) = setup(4);
+        drive(&mut car, &graph, &locks, &mut wrong);
+        assert_eq!(car.sips.faults, 3);
+
+        // Three fields back, then on along the picked branch
+        car.gear = 3;
+        car.roll_dice(Keep, &mut StdRng::seed_from_u64(1));
+        assert!(car.reversing());
+        let (fields, faults) = (car.fields_left() as i32, car.sips.faults); // Counting any rerolls
+        drive(&mut car, &graph, &locks, &mut wrong);
+
+        assert!(car.wrong_branch.is_none());
+        assert_eq!(car.position, fork + Direction::UL.to_coord() * (fields - 3));
+        assert_eq!(car.direction, Direction::UL);
+        assert_eq!(car.sips.faults, faults);
+    }
+
+    #[test]
+    fn short_roll_keeps_driving_back() {
+        let fork = Coord::new(0, 0);
+        let (mut car, graph, locks, mut wrong) = setup(6);
+        drive(&mut car, &graph, &locks, &mut wrong);
+
+        car.gear = 1;
+        car.roll_dice(Keep, &mut StdRng::seed_from_u64(1));
+        let (fields, faults) = (car.fields_left() as i32, car.sips.faults);
+        drive(&mut car, &graph, &locks, &mut wrong);
+
+        let back = 5 - fields.min(5);
+        assert_eq!(car.position, fork + Direction::UR.to_coord() * back);
+        assert_eq!(car.reversing(), back > 0);
+        assert_eq!(car.sips.faults, faults);
+    }
+}
@@ -0,0 +1,72 @@
+use crate::map::*;
+use crate::player::*;
+use crate::trace;
+use crate::util::*;
+use rand::seq::IndexedRandom;
+use sdl2::EventPump;
+
+// Special tiles as effects, asked by the engine at fixed points of a turn:
+// before the roll of a car starting on the tile, when a car drives on from it
+// during the move, and when a turn ends on it.
+// New tiles only need an effect here, not changes to the moves themselves.
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TileEffect {
+    GearDown,  // Blue tiles: one gear down at the start of the next turn
+    Rotate,    // Facing a random direction after landing
+    PitLane,   // Le Mans: entered in the pit gear at most, closed to faster cars (see `Player::pit_closed`)
+    FortyTwo,  // Le Mans: the lander names an opponent, who drinks the same as the lander this turn
+    Boost(u8), // Fields added to the move of a car driving through
+    OilSlick,  // A car starting its turn here spins out and starts in first gear
+}
+
+impl TileEffect {
+    /// Before the roll of a car starting its turn on the tile
+    pub fn start_of_turn(&self, player: &mut Player) {
+        if let TileEffect::OilSlick = self {
+            trace!("Spun out on oil");
+            player.stalled = true;
+        }
+    }
+
+    /// A car drives on from the tile during its move, after driving onto it
+    pub fn on_pass(&self, player: &mut Player) {
+        if let TileEffect::Boost(fields) = self
+            && player.steps > 0
+        {
+            player.boost += fields;
+        }
+    }
+
+    /// The turn of `player` ends on the tile
+    pub fn on_land(&self, game: &mut GameState, player: usize, event_pump: Option<&mut EventPump>) {
+        match self {
+            TileEffect::GearDown => game.players[player].forced_gear_down = true,
+            TileEffect::Rotate => {
+                game.players[player].direction = *[
+                    Direction::U,
+                    Direction::UR,
+                    Direction::DR,
+                    Direction::UL,
+                    Direction::DL,
+                    Direction::D,
+                ]
+                .choose(&mut game.rng)
+                .unwrap();
+            }
+            TileEffect::FortyTwo => {
+                let sips = game.players[player].sips.total() - game.turn_sips.total();
+                if sips == 0 {
+                    return;
+                }
+                let Some(opponent) = game.nominate(player, event_pump) else {
+                    return;
+                };
+                game.players[opponent].sips.named += sips;
+                trace!("Player {} names player {} to drink {} sips", player, opponent, sips);
+                game.events.push(GameEvent::FortyTwo { player, opponent, sips });
+            }
+            TileEffect::PitLane | TileEffect::Boost(_) | TileEffect::OilSlick => {}
+        }
+    }
+}
@@ -0,0 +1,365 @@
+use crate::map::*;
+use crate::util::*;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+// Gym-style environment around the headless engine. One seat is controlled
+// by the agent through `Action`s, the remaining seats use `PlayerGearStrategy::Best`.
+
+pub const OBSERVATION_RADIUS: i32 = 3;
+pub const OBSERVATION_HEXES: usize = (1 + 3 * OBSERVATION_RADIUS * (OBSERVATION_RADIUS + 1)) as usize;
+pub const HEX_FEATURES: usize = 15;
+pub const PLAYER_FEATURES: usize = 19;
+pub const OBSERVATION_SIZE: usize = OBSERVATION_HEXES * HEX_FEATURES + PLAYER_FEATURES;
+
+pub const ACTION_COUNT: usize = 6;
+
+pub type Observation = Vec<f32>;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Action {
+    Gear(ChangeGear),
+    Turn(Turn),
+}
+
+impl Action {
+    pub fn from_index(i: usize) -> Self {
+        match i {
+            0 => Action::Gear(ChangeGear::Down),
+            1 => Action::Gear(ChangeGear::Stay),
+            2 => Action::Gear(ChangeGear::Up),
+            3 => Action::Turn(Turn::Left),
+            4 => Action::Turn(Turn::Straight),
+            5 => Action::Turn(Turn::Right),
+            _ => panic!("Invalid action"),
+        }
+    }
+
+    pub fn to_index(self) -> usize {
+        match self {
+            Action::Gear(ChangeGear::Down) => 0,
+            Action::Gear(ChangeGear::Stay) => 1,
+            Action::Gear(ChangeGear::Up) => 2,
+            Action::Turn(Turn::Left) => 3,
+            Action::Turn(Turn::Straight) => 4,
+            Action::Turn(Turn::Right) => 5,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EnvConfig {
+    pub players: usize,     // Seats on the board, at most `player_builder.total`
+    pub agent_index: usize, // Seat controlled by the agent
+    pub laps: usize,
+    pub max_turns: usize, // Truncate episodes where the agent never finishes
+    pub turn_reward: f32, // Reward for each turn the agent starts
+    pub half_lap_reward: f32, // Reward for crossing the mid line or the start line
+}
+
+impl Default for EnvConfig {
+    fn default() -> Self {
+        EnvConfig {
+            players: 1,
+            agent_index: 0,
+            laps: 1,
+            max_turns: 500,
+            turn_reward: -0.1,
+            half_lap_reward: 1.0,
+        }
+    }
+}
+
+pub struct Env {
+    pub map: HexMap,
+    pub config: EnvConfig,
+    pub game: GameState<'static>,
+    pub turns: usize,
+}
+
+impl Env {
+    /// Environment started as if reset with seed 0
+    pub fn new(map: HexMap, config: EnvConfig) -> Self {
+        Env::with_seed(map, config, 0)
+    }
+
+    /// Environment started as if reset with `seed`
+    pub fn with_seed(mut map: HexMap, config: EnvConfig, seed: u64) -> Self {
+        map.player_builder.total = config.players;
+        let game = GameState::headless(map.clone(), Env::strategies(&config), seed);
+        let mut env = Env {
+            map,
+            config,
+            game,
+            turns: 0,
+        };
+        env.advance();
+        env
+    }
+
+    fn strategies(config: &EnvConfig) -> Vec<(PlayerGearStrategy, PlayerStepStrategy)> {
+        (0..config.players)
+            .map(|i| {
+                if i == config.agent_index {
+                    (PlayerGearStrategy::External, PlayerStepStrategy::External)
+                } else {
+                    (PlayerGearStrategy::Best, PlayerStepStrategy::Best)
+                }
+            })
+            .collect()
+    }
+
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        self.game = GameState::headless(self.map.clone(), Env::strategies(&self.config), seed);
+        self.turns = 0;
+        self.advance();
+        self.observation()
+    }
+
+    pub fn done(&self) -> bool {
+        self.game.players[self.config.agent_index].round > self.config.laps
+            || self.turns >= self.config.max_turns
+    }
+
+    /// Apply the action to the decision the agent is waiting on. An action of
+    /// the wrong kind falls back to `ChangeGear::Stay` or `Turn::Straight`.
+    pub fn step(&mut self, action: Action) -> (Observation, f32, bool) {
+        if self.done() {
+            return (self.observation(), 0.0, true);
+        }
+
+        let agent = &self.game.players[self.config.agent_index];
+        let half_laps = |round: usize, first_half: bool| 2 * round - if first_half { 1 } else { 0 };
+        let progress = half_laps(agent.round, agent.first_half);
+
+        let mut reward = 0.0;
+        match self.game.awaiting_external() {
+            Some(Decision::Gear) => {
+                self.game.external_gear = Some(match action {
+                    Action::Gear(change) => change,
+                    Action::Turn(_) => ChangeGear::Stay,
+                });
+                self.turns += 1;
+                reward += self.config.turn_reward;
+            }
+            Some(Decision::Turn(_) | Decision::Facing(_)) => {
+                self.game.external_turn = Some(match action {
+                    Action::Turn(turn) => turn,
+                    Action::Gear(_) => Turn::Straight,
+                });
+            }
+            None => {}
+        }
+        self.game.step_game(None);
+        self.advance();
+
+        let agent = &self.game.players[self.config.agent_index];
+        let new_progress = half_laps(agent.round, agent.first_half);
+        reward += self.config.half_lap_reward * (new_progress - progress) as f32;
+
+        (self.observation(), reward, self.done())
+    }
+
+    /// Run the game until the agent has to decide something
+    fn advance(&mut self) {
+        while !self.done() && self.game.awaiting_external().is_none() {
+            self.game.step_game(None);
+        }
+    }
+
+    fn neighborhood(center: Coord) -> Vec<Coord> {
+        let n = OBSERVATION_RADIUS;
+        let mut coords = vec![];
+        for q in -n..=n {
+            for r in (-n).max(-q - n)..=n.min(-q + n) {
+                coords.push(center + Coord::axel(q, r));
+            }
+        }
+        coords
+    }
+
+    pub fn observation(&self) -> Observation {
+        let agent = &self.game.players[self.config.agent_index];
+        let mut obs = Vec::with_capacity(OBSERVATION_SIZE);
+
+        let flag = |b: bool| if b { 1.0 } else { 0.0 };
+
+        for c in Env::neighborhood(agent.position) {
+            let occupied = self
+                .game
+                .players
+                .iter()
+                .enumerate()
+                .any(|(i, p)| i != self.config.agent_index && p.position == c);
+            match self.game.map.tiles.get(&c) {
+                Some(t) => {
+                    obs.extend([
+                        1.0,
+                        flag(t.start_field),
+                        flag(t.blue),
+                        flag(t.chikane),
+                        flag(t.choice),
+                        flag(t.rotate),
+                        flag(t.oneway),
+                        flag(self.game.blockages.contains(&c)),
+                        flag(occupied),
+                    ]);
+                    obs.extend((0..6).map(|d| flag(t.directions.contains(&Direction::from_num(d)))));
+                }
+                None => obs.extend([0.0; HEX_FEATURES]),
+            }
+        }
+
+        let decision = self.game.awaiting_external();
+        let turns = match &decision {
+            Some(Decision::Turn(turns)) => turns.clone(),
+            // Facings are chosen by turning from the direction the agent faces
+            Some(Decision::Facing(facings)) => [Turn::Left, Turn::Straight, Turn::Right]
+                .into_iter()
+                .filter(|t| facings.contains(&(agent.direction + *t)))
+                .collect(),
+            _ => vec![],
+        };
+        let roll: u8 = agent.roll.iter().sum();
+
+        obs.extend((1..=3).map(|g| flag(agent.gear == g)));
+        obs.push(roll as f32 / 12.0);
+        obs.push(agent.fields_left() as f32 / 12.0);
+        obs.extend((0..6).map(|d| flag(agent.direction.to_num() == d)));
+        obs.push(flag(agent.stalled));
+        obs.push(flag(agent.first_half));
+        obs.push(flag(agent.forced_gear_down));
+        obs.push(flag(decision == Some(Decision::Gear)));
+        obs.push(flag(matches!(decision, Some(Decision::Turn(_) | Decision::Facing(_)))));
+        obs.extend([Turn::Left, Turn::Straight, Turn::Right].map(|t| flag(turns.contains(&t))));
+
+        debug_assert_eq!(obs.len(), OBSERVATION_SIZE);
+        obs
+    }
+}
+
+enum Command {
+    Reset(Vec<u64>),
+    Step(Vec<Action>),
+}
+
+struct Worker {
+    commands: Sender<Command>,
+    results: Receiver<Vec<(Observation, f32, bool)>>,
+    handle: JoinHandle<()>,
+    envs: usize,
+}
+
+/// Many environments stepped in parallel, split over worker threads. Finished
+/// environments are reset automatically and return their first observation.
+pub struct VecEnv {
+    workers: Vec<Worker>,
+}
+
+impl VecEnv {
+    /// Environment `i` starts as if reset with seed `i`
+    pub fn new(map: HexMap, config: EnvConfig, envs: usize, threads: usize) -> Self {
+        let threads = threads.clamp(1, envs.max(1));
+        let total = envs;
+        let workers = (0..threads)
+            .map(|t| {
+                let count = envs / threads + if t < envs % threads { 1 } else { 0 };
+                let first = t * (envs / threads) + t.min(envs % threads); // Index of the first environment of the thread
+                let (commands, command_receiver) = channel();
+                let (result_sender, results) = channel();
+                let map = map.clone();
+                let config = config.clone();
+
+                let handle = thread::spawn(move || {
+                    set_verbose(false);
+                    let mut seeds: Vec<u64> = (first..first + count).map(|i| i as u64).collect();
+                    let mut envs: Vec<Env> = seeds
+                        .iter()
+                        .map(|seed| Env::with_seed(map.clone(), config.clone(), *seed))
+                        .collect();
+
+                    while let Ok(command) = command_receiver.recv() {
+                        let result = match command {
+                            Command::Reset(new_seeds) => {
+                                seeds = new_seeds;
+                                envs.iter_mut()
+                                    .zip(&seeds)
+                                    .map(|(env, seed)| (env.reset(*seed), 0.0, false))
+                                    .collect()
+                            }
+                            Command::Step(actions) => envs
+                                .iter_mut()
+                                .zip(actions)
+                                .zip(seeds.iter_mut())
+                                .map(|((env, action), seed)| {
+                                    let (obs, reward, done) = env.step(action);
+                                    if done {
+                                        // Every environment steps by the same `total`, so seeds never meet
+                                        *seed = seed.wrapping_add(total as u64);
+                                        (env.reset(*seed), reward, done)
+                                    } else {
+                                        (obs, reward, done)
+                                    }
+                                })
+                                .collect(),
+                        };
+                        if result_sender.send(result).is_err() {
+                            break;
+                        }
+                    }
+                });
+
+                Worker {
+                    commands,
+                    results,
+                    handle,
+                    envs: count,
+                }
+            })
+            .collect();
+
+        VecEnv { workers }
+    }
+
+    pub fn len(&self) -> usize {
+        self.workers.iter().map(|w| w.envs).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Environment `i` is reset with seed `seed + i`
+    pub fn reset(&mut self, seed: u64) -> Vec<Observation> {
+        let mut index = 0;
+        for w in &self.workers {
+            let seeds = (index..index + w.envs).map(|i| seed.wrapping_add(i as u64)).collect();
+            index += w.envs;
+            w.commands.send(Command::Reset(seeds)).unwrap();
+        }
+        self.collect().into_iter().map(|(obs, _, _)| obs).collect()
+    }
+
+    pub fn step(&mut self, actions: Vec<Action>) -> Vec<(Observation, f32, bool)> {
+        assert_eq!(actions.len(), self.len());
+        let mut actions = actions.into_iter();
+        for w in &self.workers {
+            w.commands.send(Command::Step(actions.by_ref().take(w.envs).collect())).unwrap();
+        }
+        self.collect()
+    }
+
+    fn collect(&mut self) -> Vec<(Observation, f32, bool)> {
+        self.workers
+            .iter()
+            .flat_map(|w| w.results.recv().unwrap())
+            .collect()
+    }
+
+    pub fn close(self) {
+        for w in self.workers {
+            drop(w.commands);
+            let _ = w.handle.join();
+        }
+    }
+}
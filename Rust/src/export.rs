@@ -0,0 +1,380 @@
+use crate::canvas_draw::*;
+use crate::map::*;
+use crate::player::*;
+use crate::replay::*;
+use crate::util::*;
+use sdl2::pixels::Color;
+use sdl2::rect::Point;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+
+// Offscreen rendering of boards and race snapshots to PNG and SVG
+
+pub const EXPORT_SCALE: f64 = 36.0;
+pub const CAPTION_SIZE: f64 = 20.0;
+pub const FRAME_DELAY: u16 = 12; // Hundredths of a second per frame of an animation
+
+// RGB pixel buffer
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+    color: Color,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32) -> Self {
+        Image {
+            width,
+            height,
+            pixels: vec![0; (width * height * 3) as usize],
+            color: Color::RGB(0, 0, 0),
+        }
+    }
+
+    pub fn clear(&mut self, color: Color) {
+        for p in self.pixels.chunks_mut(3) {
+            p.copy_from_slice(&[color.r, color.g, color.b]);
+        }
+    }
+
+    fn put(&mut self, x: i32, y: i32) {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return;
+        }
+        let i = ((y as u32 * self.width + x as u32) * 3) as usize;
+        self.pixels[i..i + 3].copy_from_slice(&[self.color.r, self.color.g, self.color.b]);
+    }
+
+    pub fn write_png(&self, writer: impl Write) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        Ok(())
+    }
+
+    pub fn save_png(&self, path: &Path) -> io::Result<()> {
+        self.write_png(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn load_png(path: &Path) -> io::Result<Self> {
+        let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info().map_err(io::Error::other)?;
+        let mut buffer = vec![0; reader.output_buffer_size().ok_or_else(|| io::Error::other("Image too large"))?];
+        let info = reader.next_frame(&mut buffer).map_err(io::Error::other)?;
+
+        let channels = match info.color_type {
+            png::ColorType::Grayscale => 1,
+            png::ColorType::GrayscaleAlpha => 2,
+            png::ColorType::Rgb => 3,
+            png::ColorType::Rgba => 4,
+            png::ColorType::Indexed => return Err(io::Error::other("Unexpanded palette")),
+        };
+        let mut image = Image::new(info.width, info.height);
+        for (pixel, source) in image.pixels.chunks_mut(3).zip(buffer[..info.buffer_size()].chunks(channels)) {
+            match channels {
+                1 | 2 => pixel.copy_from_slice(&[source[0]; 3]),
+                _ => pixel.copy_from_slice(&source[..3]),
+            }
+        }
+        Ok(image)
+    }
+
+    pub fn pixel(&self, x: i32, y: i32) -> Option<Color> {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return None;
+        }
+        let i = ((y as u32 * self.width + x as u32) * 3) as usize;
+        Some(Color::RGB(self.pixels[i], self.pixels[i + 1], self.pixels[i + 2]))
+    }
+}
+
+impl Painter for Image {
+    fn set_draw_color(&mut self, color: Color) {
+        self.color = color;
+    }
+
+    // Bresenham
+    fn draw_line<P1: Into<Point>, P2: Into<Point>>(&mut self, start: P1, end: P2) -> Result<(), String> {
+        let (start, end) = (start.into(), end.into());
+        let (mut x, mut y) = (start.x(), start.y());
+        let dx = (end.x() - x).abs();
+        let dy = -(end.y() - y).abs();
+        let sx = if x < end.x() { 1 } else { -1 };
+        let sy = if y < end.y() { 1 } else { -1 };
+        let mut err = dx + dy;
+        loop {
+            self.put(x, y);
+            if x == end.x() && y == end.y() {
+                return Ok(());
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    fn draw_lines(&mut self, points: &[Point]) -> Result<(), String> {
+        for w in points.windows(2) {
+            self.draw_line(w[0], w[1])?;
+        }
+        Ok(())
+    }
+}
+
+// Vector drawing, one element per line
+pub struct Svg {
+    pub width: u32,
+    pub height: u32,
+    pub millimetres: Option<(f64, f64)>, // Printed size, otherwise one unit is a pixel
+    pub elements: Vec<String>,
+    color: Color,
+}
+
+fn svg_color(c: Color) -> String {
+    format!("rgb({},{},{})", c.r, c.g, c.b)
+}
+
+fn svg_points(points: &[Point]) -> String {
+    points
+        .iter()
+        .map(|p| format!("{},{}", p.x(), p.y()))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+impl Svg {
+    pub fn new(width: u32, height: u32) -> Self {
+        Svg {
+            width,
+            height,
+            millimetres: None,
+            elements: vec![],
+            color: Color::RGB(0, 0, 0),
+        }
+    }
+
+    pub fn clear(&mut self, color: Color) {
+        self.elements.clear();
+        self.elements.push(format!(
+            "<rect width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+            self.width,
+            self.height,
+            svg_color(color)
+        ));
+    }
+
+    pub fn write_svg(&self, mut writer: impl Write) -> io::Result<()> {
+        let (width, height) = match self.millimetres {
+            Some((w, h)) => (format!("{}mm", w), format!("{}mm", h)),
+            None => (self.width.to_string(), self.height.to_string()),
+        };
+        writeln!(
+            writer,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">",
+            width, height, self.width, self.height
+        )?;
+        for e in &self.elements {
+            writeln!(writer, "{}", e)?;
+        }
+        writeln!(writer, "</svg>")
+    }
+
+    pub fn save_svg(&self, path: &Path) -> io::Result<()> {
+        self.write_svg(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl Painter for Svg {
+    fn set_draw_color(&mut self, color: Color) {
+        self.color = color;
+    }
+
+    fn draw_line<P1: Into<Point>, P2: Into<Point>>(&mut self, start: P1, end: P2) -> Result<(), String> {
+        let (start, end) = (start.into(), end.into());
+        self.elements.push(format!(
+            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"{}\"/>",
+            start.x(),
+            start.y(),
+            end.x(),
+            end.y(),
+            svg_color(self.color)
+        ));
+        Ok(())
+    }
+
+    fn draw_lines(&mut self, points: &[Point]) -> Result<(), String> {
+        self.elements.push(format!(
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\"/>",
+            svg_points(points),
+            svg_color(self.color)
+        ));
+        Ok(())
+    }
+
+    // One polygon instead of nested outlines
+    fn fill_hexagon(&mut self, x: i32, y: i32, scale: f64) {
+        self.elements.push(format!(
+            "<polygon points=\"{}\" fill=\"{}\"/>",
+            svg_points(&hexagon_points(x, y, scale)),
+            svg_color(self.color)
+        ));
+    }
+}
+
+/// Placement of the board in an exported picture
+pub struct Frame {
+    pub start: Coord,
+    pub scale: f64,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Frame {
+    /// Smallest frame holding every tile of the board
+    pub fn fit(map: &HexMap, scale: f64) -> Self {
+        let margin = (scale * 1.5) as i32;
+        let pixels = |start: Coord| map.tiles.keys().map(move |c| start + *c * (scale as i32));
+
+        let min_x = pixels(Coord::axel(0, 0)).map(|c| c.x()).min().unwrap_or(0);
+        let min_y = pixels(Coord::axel(0, 0)).map(|c| c.y()).min().unwrap_or(0);
+        let start = Coord::new(margin - min_x, margin - min_y);
+
+        let max_x = pixels(start).map(|c| c.x()).max().unwrap_or(0);
+        let max_y = pixels(start).map(|c| c.y()).max().unwrap_or(0);
+        Frame {
+            start,
+            scale,
+            width: (max_x + margin).max(1) as u32,
+            height: (max_y + margin).max(1) as u32,
+        }
+    }
+}
+
+/// Board with the given players on top, each trailed by the positions it visited
+pub fn draw_snapshot(
+    canvas: &mut impl Painter,
+    map: &HexMap,
+    players: &[Player],
+    trails: &[Vec<Coord>],
+    frame: &Frame,
+) {
+    map.draw(canvas, frame.start, frame.scale);
+
+    for (p, trail) in players.iter().zip(trails) {
+        canvas.set_draw_color(p.color);
+        for w in trail.windows(2) {
+            let from = frame.start + w[0] * (frame.scale as i32);
+            let to = frame.start + w[1] * (frame.scale as i32);
+            for i in -1..=1 {
+                for j in -1..=1 {
+                    let _ = canvas.draw_line((from.x() + i, from.y() + j), (to.x() + i, to.y() + j));
+                }
+            }
+        }
+    }
+
+    for p in players {
+        p.draw(canvas, frame.start, frame.scale);
+    }
+}
+
+/// Write a snapshot to `path`, as SVG if the extension is `.svg` and PNG otherwise
+pub fn export(
+    path: &Path,
+    map: &HexMap,
+    players: &[Player],
+    trails: &[Vec<Coord>],
+    scale: f64,
+) -> io::Result<()> {
+    let frame = Frame::fit(map, scale);
+    if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("svg")) {
+        let mut svg = Svg::new(frame.width, frame.height);
+        svg.clear(Color::RGB(0, 0, 0));
+        draw_snapshot(&mut svg, map, players, trails, &frame);
+        svg.save_svg(path)
+    } else {
+        let mut image = Image::new(frame.width, frame.height);
+        draw_snapshot(&mut image, map, players, trails, &frame);
+        image.save_png(path)
+    }
+}
+
+/// Name, gear and roll of a player
+pub fn caption(name: &str, player: &Player) -> String {
+    let roll = if player.roll.is_empty() {
+        "-".to_string()
+    } else {
+        let dice: Vec<String> = player.roll.iter().map(|r| r.to_string()).collect();
+        format!("{}={}", dice.join("+"), player.roll.iter().sum::<u8>())
+    };
+    format!("{}  Gear {}  Roll {}", name, player.gear, roll)
+}
+
+/// Snapshot of a step in a race, captioned with the roll and gear of the active player
+pub fn draw_frame(
+    canvas: &mut impl Painter,
+    map: &HexMap,
+    active: usize,
+    players: &[Player],
+    trails: &[Vec<Coord>],
+    frame: &Frame,
+) {
+    draw_snapshot(canvas, map, players, trails, frame);
+    if let Some(p) = players.get(active) {
+        canvas.set_draw_color(p.color);
+        for i in 0..=1 {
+            draw_text(canvas, &caption(&format!("Player {}", active + 1), p), 8 + i, 8, CAPTION_SIZE);
+        }
+    }
+}
+
+/// Write every step of a replay as an animated GIF if `path` ends with `.gif`,
+/// otherwise as numbered PNG frames in the directory `path`
+pub fn animate(path: &Path, map: &HexMap, replay: &Replay, scale: f64) -> io::Result<()> {
+    let mut frame = Frame::fit(map, scale);
+    // Room for the caption above the board
+    let rows = (CAPTION_SIZE * 1.5 / 3_f64.sqrt()).ceil() as i32;
+    frame.start = frame.start + Coord::axel(0, rows);
+    frame.height += (rows as f64 * 3_f64.sqrt()).ceil() as u32;
+
+    let gif = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("gif"));
+    let mut encoder = if gif {
+        let (Ok(width), Ok(height)) = (u16::try_from(frame.width), u16::try_from(frame.height)) else {
+            return Err(io::Error::other("Board too large for a GIF"));
+        };
+        let mut encoder = gif::Encoder::new(BufWriter::new(File::create(path)?), width, height, &[]).map_err(io::Error::other)?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(io::Error::other)?;
+        Some(encoder)
+    } else {
+        fs::create_dir_all(path)?;
+        None
+    };
+
+    let frames = replay.frames(map);
+    for (n, (active, players, trails)) in frames.iter().enumerate() {
+        let mut image = Image::new(frame.width, frame.height);
+        draw_frame(&mut image, map, *active, players, trails, &frame);
+
+        match encoder.as_mut() {
+            Some(encoder) => {
+                let mut gif_frame = gif::Frame::from_rgb_speed(frame.width as u16, frame.height as u16, &image.pixels, 30);
+                // Hold the final position
+                gif_frame.delay = if n + 1 == frames.len() { FRAME_DELAY * 25 } else { FRAME_DELAY };
+                encoder.write_frame(&gif_frame).map_err(io::Error::other)?;
+            }
+            None => image.save_png(&path.join(format!("frame_{:05}.png", n)))?,
+        }
+    }
+    Ok(())
+}
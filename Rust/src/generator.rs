@@ -0,0 +1,476 @@
+use crate::boards::*;
+use crate::effects::*;
+use crate::graph::*;
+use crate::map::*;
+use crate::util::*;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeMap, BTreeSet};
+use std::f64::consts::PI;
+
+// Closed tracks around a wobbly circle, like `gen_map.py`. The lanes follow
+// the curve around the center, then forks and loops are grown off the outer
+// lane and the rest of the tile types are spread over the lap.
+
+const ATTEMPTS: usize = 100;
+const PLAYERS: usize = 8;
+const LANE: f64 = 1.25; // Distance between the lanes, a bit closer than the rows of hexes
+
+#[derive(Clone, Debug)]
+pub struct TrackConfig {
+    pub seed: u64,
+    pub length: usize,     // Tiles around the outer lane
+    pub width: usize,      // Lanes side by side
+    pub corners: usize,    // Bumps of the curve
+    pub chikanes: usize,
+    pub blue_zones: usize, // Stretches over every lane
+    pub forks: usize,      // Choice tiles splitting into two branches
+    pub loops: usize,      // Tracks crossing themselves with forced directions
+    pub pit: usize,        // Tiles of the inner lane before the grid forming a pit lane
+    pub forty_two: usize,  // Le Mans 42 fields on the outer lane
+}
+
+impl Default for TrackConfig {
+    fn default() -> Self {
+        TrackConfig {
+            seed: 0,
+            length: 60,
+            width: 2,
+            corners: 4,
+            chikanes: 4,
+            blue_zones: 2,
+            forks: 1,
+            loops: 1,
+            pit: 0,
+            forty_two: 0,
+        }
+    }
+}
+
+impl TrackConfig {
+    /// `<seed>` followed by any of `,length=`, `,width=`, `,corners=`,
+    /// `,chikanes=`, `,blue=`, `,forks=`, `,loops=`, `,pit=` and `,42=`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut parts = spec.split(',');
+        let seed = parts.next().unwrap_or_default();
+        let mut config = TrackConfig {
+            seed: seed.parse().map_err(|_| format!("Invalid seed {}", seed))?,
+            ..TrackConfig::default()
+        };
+        for part in parts {
+            let Some((key, value)) = part.split_once('=') else {
+                return Err(format!("Expected key=value, found {}", part));
+            };
+            let value: usize = value.parse().map_err(|_| format!("Invalid number {}", value))?;
+            match key {
+                "length" => config.length = value,
+                "width" => config.width = value,
+                "corners" => config.corners = value,
+                "chikanes" => config.chikanes = value,
+                "blue" => config.blue_zones = value,
+                "forks" => config.forks = value,
+                "loops" => config.loops = value,
+                "pit" => config.pit = value,
+                "42" => config.forty_two = value,
+                _ => return Err(format!("Unknown option {}", key)),
+            }
+        }
+        Ok(config)
+    }
+}
+
+// Radius of the track around the center, as a fraction of the mean radius
+struct Shape {
+    waves: Vec<(f64, f64, f64)>, // Frequency, amplitude and phase
+}
+
+impl Shape {
+    fn random(rng: &mut impl Rng, corners: usize) -> Self {
+        let mut waves = vec![];
+        for f in [2.0, 3.0] {
+            waves.push((f, rng.random_range(0.0..0.08), rng.random_range(0.0..2.0 * PI)));
+        }
+        if corners > 0 {
+            waves.push((corners as f64, rng.random_range(0.1..0.3), rng.random_range(0.0..2.0 * PI)));
+            waves.push((corners as f64 + 1.0, rng.random_range(0.0..0.08), rng.random_range(0.0..2.0 * PI)));
+        }
+        Shape { waves }
+    }
+
+    fn point(&self, angle: f64, radius: f64) -> (f64, f64) {
+        let r = radius * (1.0 + self.waves.iter().map(|(f, a, p)| a * (f * angle + p).cos()).sum::<f64>());
+        (r * angle.cos(), r * angle.sin())
+    }
+}
+
+// Hex under a point, in units of the distance from the center of a hex to a corner
+fn hex_at(x: f64, y: f64) -> Coord {
+    let q = x * 2.0 / 3.0;
+    let r = -x / 3.0 + y / 3_f64.sqrt();
+    let s = -q - r;
+    let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+    if (rq - q).abs() > (rr - r).abs() && (rq - q).abs() > (rs - s).abs() {
+        rq = -rr - rs;
+    } else if (rr - r).abs() > (rs - s).abs() {
+        rr = -rq - rs;
+    }
+    Coord::axel(rq as i32, rr as i32)
+}
+
+fn direction_to(from: Coord, to: Coord) -> Option<Direction> {
+    (0..6).map(Direction::from_num).find(|d| from + d.to_coord() == to)
+}
+
+fn turnable(from: Direction, to: Direction) -> bool {
+    from.turn_to_dir(to).is_some()
+}
+
+// Tiles of a lane `offset` hexes inside the curve, with the direction to
+// the next tile and the part of the lap at the middle of the tile
+fn lane(shape: &Shape, radius: f64, offset: f64) -> Option<Vec<(Coord, Direction, f64)>> {
+    let samples = (radius * 400.0) as usize;
+    let mut cells: Vec<(Coord, f64, f64)> = vec![];
+    for i in 0..samples {
+        let angle = 2.0 * PI * i as f64 / samples as f64;
+        let (x, y) = shape.point(angle, radius);
+        let (ax, ay) = shape.point(angle + 1e-4, radius);
+        let (bx, by) = shape.point(angle - 1e-4, radius);
+        let (tx, ty) = (ax - bx, ay - by);
+        let length = (tx * tx + ty * ty).sqrt();
+        // Normal towards the center
+        let (mut nx, mut ny) = (-ty / length, tx / length);
+        if nx * x + ny * y > 0.0 {
+            (nx, ny) = (-nx, -ny);
+        }
+        let c = hex_at(x + nx * offset * LANE, y + ny * offset * LANE);
+        match cells.last_mut() {
+            Some((last, _, end)) if *last == c => *end = angle,
+            _ => cells.push((c, angle, angle)),
+        }
+    }
+    if cells.len() > 1 && cells[0].0 == cells.last()?.0 {
+        cells.pop();
+    }
+    // Skip tiles the lane zigzags over, where the curve runs between two rows
+    let mut i = 0;
+    while cells.len() > 6 && i < cells.len() {
+        let (before, after) = ((i + cells.len() - 1) % cells.len(), (i + 1) % cells.len());
+        if direction_to(cells[before].0, cells[after].0).is_some() {
+            if after > i {
+                cells[after].1 = cells[i].1;
+            }
+            cells.remove(i);
+            i = i.saturating_sub(1);
+        } else {
+            i += 1;
+        }
+    }
+
+    let unique: BTreeSet<Coord> = cells.iter().map(|(c, _, _)| *c).collect();
+    if cells.len() < 6 || unique.len() != cells.len() {
+        return None; // Crosses itself
+    }
+
+    let mut result = vec![];
+    for (i, (c, start, end)) in cells.iter().enumerate() {
+        let next = cells[(i + 1) % cells.len()].0;
+        result.push((*c, direction_to(*c, next)?, (start + end) / 2.0 / (2.0 * PI)));
+    }
+    for i in 0..result.len() {
+        let previous = result[(i + result.len() - 1) % result.len()].1;
+        if !turnable(previous, result[i].1) {
+            return None;
+        }
+    }
+    Some(result)
+}
+
+// New tiles from `from` leaving in `heading`, until `arrive` accepts an
+// existing tile and the heading it is entered with
+fn detour(
+    tiles: &BTreeMap<Coord, Tile>,
+    path: &mut Vec<(Coord, Direction)>,
+    from: Coord,
+    heading: Direction,
+    range: (usize, usize),
+    arrive: &impl Fn(Coord, Direction) -> bool,
+) -> bool {
+    let next = from + heading.to_coord();
+    if path.len() >= range.0 && arrive(next, heading) {
+        return true;
+    }
+    if path.len() >= range.1 || tiles.contains_key(&next) || path.iter().any(|(c, _)| *c == next) {
+        return false;
+    }
+    for turn in [Turn::Straight, Turn::Left, Turn::Right] {
+        path.push((next, heading + turn));
+        if detour(tiles, path, next, heading + turn, range, arrive) {
+            return true;
+        }
+        path.pop();
+    }
+    false
+}
+
+fn ahead(from: f64, to: f64) -> f64 {
+    (to - from).rem_euclid(1.0)
+}
+
+/// Every tile can be reached from the grid, and the mid line and the goal
+/// can be reached from everywhere on the track
+pub fn valid(map: &HexMap) -> bool {
+    let graph = map.graph();
+    let reached = graph.reachable(map.player_builder.placements.iter().copied());
+    if reached.iter().map(|n| n.0).collect::<BTreeSet<Coord>>().len() != map.tiles.len() {
+        return false;
+    }
+
+    [&map.start_line, &map.mid_line].iter().all(|line| {
+        let mut done: BTreeSet<Node> = reached
+            .iter()
+            .filter(|(c, d)| line.iter().any(|(l, dirs)| l == c && dirs.contains(d)))
+            .copied()
+            .collect();
+        loop {
+            let more: Vec<Node> = reached
+                .iter()
+                .filter(|n| !done.contains(n) && graph.marked_moves(**n).any(|e| done.contains(&e.to)))
+                .copied()
+                .collect();
+            if more.is_empty() {
+                break;
+            }
+            done.extend(more);
+        }
+        done.len() == reached.len()
+    })
+}
+
+/// Reproducible track for the seed of `config`
+pub fn generate(config: &TrackConfig) -> Result<HexMap, String> {
+    if config.length < 20 || !(1..=3).contains(&config.width) {
+        return Err("Tracks need a length of at least 20 and 1 to 3 lanes".to_string());
+    }
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    for _ in 0..ATTEMPTS {
+        let shape = Shape::random(&mut rng, config.corners);
+        if let Some(map) = build(config, &shape, &mut rng) {
+            return Ok(map);
+        }
+    }
+    Err(format!("No valid track found for seed {}", config.seed))
+}
+
+fn build(config: &TrackConfig, shape: &Shape, rng: &mut impl Rng) -> Option<HexMap> {
+    // Scale the curve until the outer lane has about the right length
+    let mut radius = config.length as f64 * 3_f64.sqrt() / (2.0 * PI) / 1.15;
+    let mut outer = lane(shape, radius, 0.0)?;
+    for _ in 0..5 {
+        let ratio = config.length as f64 / outer.len() as f64;
+        if (ratio - 1.0).abs() < 0.05 {
+            break;
+        }
+        radius *= ratio;
+        outer = lane(shape, radius, 0.0)?;
+    }
+    let lanes: Vec<Vec<(Coord, Direction, f64)>> = (0..config.width)
+        .map(|k| lane(shape, radius, k as f64))
+        .collect::<Option<_>>()?;
+
+    let mut tiles: BTreeMap<Coord, Tile> = BTreeMap::new();
+    let mut progress: BTreeMap<Coord, f64> = BTreeMap::new();
+    for (c, d, p) in lanes.iter().flatten() {
+        let tile = tiles.entry(*c).or_default();
+        if !tile.directions.contains(d) {
+            tile.directions.push(*d);
+        }
+        progress.entry(*c).or_insert(*p);
+    }
+
+    // Changing lanes, to tiles a bit further ahead
+    let step = 1.0 / outer.len() as f64;
+    let headings: Vec<(Coord, Vec<Direction>)> = tiles.iter().map(|(c, t)| (*c, t.directions.clone())).collect();
+    for (c, own) in &headings {
+        for h in own {
+            for d in [*h + Turn::Left, *h + Turn::Right] {
+                let n = *c + d.to_coord();
+                let fits = tiles.get(&n).is_some_and(|t| t.directions.iter().any(|g| turnable(d, *g)));
+                let forward = progress.get(&n).is_some_and(|p| (0.0..2.0 * step).contains(&ahead(progress[c], *p)));
+                if fits && forward && !tiles[c].directions.contains(&d) {
+                    tiles.get_mut(c).unwrap().directions.push(d);
+                }
+            }
+        }
+    }
+
+    // Starting grid right before the goal, pole position first
+    let mut grid = 1;
+    while tiles.keys().filter(|c| progress[*c] >= 1.0 - grid as f64 * step).count() < PLAYERS {
+        grid += 1;
+    }
+    for (c, t) in tiles.iter_mut() {
+        if progress[c] >= 1.0 - grid as f64 * step {
+            *t = t.clone().start();
+        }
+    }
+
+    // Pit lane on the inner lane, leading onto the grid. Tiles shared with
+    // another lane stay open, so faster cars can drive around the pit lane.
+    let pit_end = 1.0 - grid as f64 * step;
+    let (inner, others) = lanes.split_last()?;
+    let shared: BTreeSet<Coord> = others.iter().flatten().map(|(c, _, _)| *c).collect();
+    for (c, _, p) in inner {
+        if ahead(*p, pit_end) < config.pit as f64 * step && !tiles[c].start_field && !shared.contains(c) {
+            tiles.insert(*c, tiles[c].clone().pit());
+        }
+    }
+
+    // Room for forks and loops, away from the grid and the lines
+    let n = outer.len();
+    let mid = (0..n).min_by(|a, b| (outer[*a].2 - 0.5).abs().total_cmp(&(outer[*b].2 - 0.5).abs()))?;
+    let mut used: Vec<bool> = (0..n)
+        .map(|i| tiles[&outer[i].0].start_field || i < 2 || i.abs_diff(mid) <= 2)
+        .collect();
+    let free = |used: &Vec<bool>, from: usize, to: usize| (from..=to).all(|i| i < n && !used[i]);
+
+    let mut indices: Vec<usize> = (1..n).collect();
+    for _ in 0..config.forks {
+        indices.shuffle(rng);
+        let fork = indices.iter().find_map(|&i| {
+            let (f, main) = (outer[i].0, outer[i].1);
+            let arrival = outer[i - 1].1;
+            if !free(&used, i - 1, i + 2) {
+                return None;
+            }
+            [arrival + Turn::Left, arrival, arrival + Turn::Right]
+                .into_iter()
+                .filter(|a| *a != main)
+                .find_map(|a| {
+                    let arrive = |c: Coord, g: Direction| {
+                        (i + 2..(i + 7).min(n)).any(|j| outer[j].0 == c && turnable(g, outer[j].1) && free(&used, i, j))
+                    };
+                    let mut path = vec![];
+                    detour(&tiles, &mut path, f, a, (2, 8), &arrive).then_some((i, a, path))
+                })
+        });
+        let (i, a, path) = fork?;
+        let (f, main) = (outer[i].0, outer[i].1);
+        let last = path.last()?;
+        let target = last.0 + last.1.to_coord();
+        let j = (i..n).find(|j| outer[*j].0 == target)?;
+
+        let span = ahead(progress[&f], progress[&target]);
+        for (k, (c, d)) in path.iter().enumerate() {
+            tiles.insert(*c, Tile::new(vec![*d]));
+            progress.insert(*c, (progress[&f] + span * (k + 1) as f64 / (path.len() + 1) as f64).rem_euclid(1.0));
+        }
+        tiles.insert(f, Tile { directions: vec![main, a], ..tiles[&f].clone() }.choice());
+        used[i - 1..=j].iter_mut().for_each(|u| *u = true);
+    }
+
+    for _ in 0..config.loops {
+        indices.shuffle(rng);
+        let crossing_loop = indices.iter().find_map(|&i| {
+            let (x, main) = (outer[i].0, outer[i].1);
+            let arrival = outer[i - 1].1;
+            if !free(&used, i - 1, i + 1) {
+                return None;
+            }
+            [arrival + Turn::Left, arrival + Turn::Right]
+                .into_iter()
+                .filter(|a| *a != main)
+                .find_map(|a| {
+                    let arrive = |c: Coord, g: Direction| c == x && turnable(g, main);
+                    let mut path = vec![];
+                    detour(&tiles, &mut path, x, a, (4, 9), &arrive).then_some((i, a, path))
+                })
+        });
+        let (i, a, path) = crossing_loop?;
+        let (x, main) = (outer[i].0, outer[i].1);
+        for (c, d) in &path {
+            tiles.insert(*c, Tile::new(vec![*d]));
+            progress.insert(*c, progress[&x]);
+        }
+        let forced = BTreeMap::from([(outer[i - 1].0, a), (path.last()?.0, main)]);
+        tiles.insert(x, Tile { directions: vec![main, a], ..tiles[&x].clone() }.forced(forced));
+        used[i - 1..=i + 1].iter_mut().for_each(|u| *u = true);
+    }
+
+    // Only the lane leads into forks and loops, turning onto them from the side would fall off
+    let special: Vec<Coord> = tiles.iter().filter(|(_, t)| t.choice || !t.forced.is_empty()).map(|(c, _)| *c).collect();
+    for s in &special {
+        let i = outer.iter().position(|(c, _, _)| c == s)?;
+        let before = outer[(i + n - 1) % n].0;
+        for (c, t) in tiles.iter_mut() {
+            if *c != before && t.directions.len() > 1 {
+                t.directions.retain(|d| *c + d.to_coord() != *s);
+            }
+        }
+    }
+
+    // Chikanes in the corners, spread over the lap
+    let mut corners: Vec<usize> = (1..n)
+        .filter(|i| outer[i - 1].1 != outer[*i].1 && !tiles[&outer[*i].0].start_field)
+        .collect();
+    corners.shuffle(rng);
+    let spacing = n / (2 * config.chikanes.max(1));
+    let mut chikanes: Vec<usize> = vec![];
+    for i in corners {
+        if chikanes.len() < config.chikanes && chikanes.iter().all(|j| i.abs_diff(*j).min(n - i.abs_diff(*j)) >= spacing) {
+            chikanes.push(i);
+        }
+    }
+    if chikanes.len() < config.chikanes {
+        return None;
+    }
+    for i in chikanes {
+        let c = outer[i].0;
+        tiles.insert(c, tiles[&c].clone().chikane());
+    }
+
+    // Blue zones across the track on the rest of the lap
+    let mut zones: Vec<usize> = (0..n).filter(|i| !tiles[&outer[*i].0].start_field && *i + 3 < n).collect();
+    zones.shuffle(rng);
+    let mut placed: Vec<usize> = vec![];
+    for i in zones {
+        if placed.len() < config.blue_zones && placed.iter().all(|j| i.abs_diff(*j) > 5) {
+            placed.push(i);
+        }
+    }
+    if placed.len() < config.blue_zones {
+        return None;
+    }
+    for i in placed {
+        let (from, length) = (outer[i].2, rng.random_range(2..=3) as f64 * step);
+        for (c, t) in tiles.iter_mut() {
+            if ahead(from, progress[c]) < length && !t.start_field && !t.is_pit() {
+                *t = t.clone().blue();
+            }
+        }
+    }
+
+    // 42 fields on plain tiles of the outer lane
+    let mut plain: Vec<Coord> = outer
+        .iter()
+        .map(|(c, _, _)| *c)
+        .filter(|c| {
+            let t = &tiles[c];
+            !(t.start_field || t.chikane || t.blue || t.choice || t.is_pit() || !t.forced.is_empty())
+        })
+        .collect();
+    plain.shuffle(rng);
+    for c in plain.into_iter().take(config.forty_two) {
+        tiles.insert(c, tiles[&c].clone().effect(TileEffect::FortyTwo));
+    }
+
+    let mut map = HexMap {
+        tiles,
+        start_line: vec![],
+        mid_line: vec![],
+        player_builder: PlayerBuilder::new(0, player_colors(), vec![]),
+    };
+    map.detect_lines();
+    map.player_builder.total = map.player_builder.total.min(PLAYERS);
+    valid(&map).then_some(map)
+}
@@ -0,0 +1,187 @@
+use crate::util::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+// Movement rules of a board as a directed graph. A node is a tile and the
+// direction the player leaves it in, an edge is the turn taken on the tile
+// entered next. Built once per board and shared by pathfinding, the AI and
+// the moves of `Player`, so `directions`, `forced`, `choice`, `oneway` and
+// `blockage` are only interpreted here.
+
+pub type Node = (Coord, Direction);
+
+#[derive(Copy, Clone, Debug)]
+pub struct Edge {
+    pub turn: Turn,
+    pub to: Node,
+    pub marked: bool, // Follows the arrows of the tile (or its forced direction)
+}
+
+/// What becomes of a turn a player takes on a tile
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Verdict {
+    Drive(Turn),
+    WrongBranch(Turn), // Down the other branch than picked for this lap, the turn onto the picked one
+    FallsOff(Turn),    // Off the track with the next step
+}
+
+#[derive(Clone, Default)]
+pub struct TrackGraph {
+    tiles: BTreeMap<Coord, Tile>,
+    edges: BTreeMap<Node, Vec<Edge>>,
+    reverse: BTreeMap<Node, Vec<Node>>,
+}
+
+/// Directions the arrows of `tile` point in when entered from `from`
+pub fn marked_directions(tile: &Tile, from: Coord) -> Vec<Direction> {
+    match tile.forced.get(&from) {
+        Some(d) => vec![*d],
+        None => tile.directions.clone(),
+    }
+}
+
+/// Leaving `tile` in `direction` keeps the player on the track, whichever way
+/// it was entered
+fn can_leave(tile: &Tile, direction: Direction) -> bool {
+    !tile.blockage.contains(&direction) && (!(tile.choice || tile.oneway) || tile.directions.contains(&direction))
+}
+
+/// Driving from `from` to `next` enters the pit lane
+pub fn enters_pit(tiles: &BTreeMap<Coord, Tile>, from: Coord, next: Coord) -> bool {
+    tiles.get(&next).is_some_and(Tile::is_pit) && !tiles.get(&from).is_some_and(Tile::is_pit)
+}
+
+// Backwards out of a loop, from `from` into a tile leading to it
+fn backwards(tiles: &BTreeMap<Coord, Tile>, from: Coord, next: Coord) -> bool {
+    tiles.get(&from).is_some_and(|t| t.forced.contains_key(&next)) && !tiles.get(&next).is_some_and(|t| t.forced.contains_key(&from))
+}
+
+// Moves on the tile ahead of `from` when driving onto it in `direction`
+fn entry_moves(tiles: &BTreeMap<Coord, Tile>, from: Coord, direction: Direction) -> Vec<Edge> {
+    let next = from + direction.to_coord();
+    let Some(next_tile) = tiles.get(&next) else {
+        return vec![]; // Falls off the board
+    };
+    if backwards(tiles, from, next) {
+        return vec![];
+    }
+    let marked = marked_directions(next_tile, from);
+    [Turn::Straight, Turn::Left, Turn::Right]
+        .into_iter()
+        .map(|turn| (turn, direction + turn))
+        .filter(|(_, new_d)| can_leave(next_tile, *new_d))
+        .filter(|(_, new_d)| !next_tile.forced.contains_key(&from) || marked.contains(new_d))
+        .map(|(turn, new_d)| Edge {
+            turn,
+            to: (next, new_d),
+            marked: marked.contains(&new_d),
+        })
+        .collect()
+}
+
+impl TrackGraph {
+    pub fn new(tiles: &BTreeMap<Coord, Tile>) -> Self {
+        let mut edges: BTreeMap<Node, Vec<Edge>> = BTreeMap::new();
+        for (c, tile) in tiles {
+            for d in (0..6).map(Direction::from_num).filter(|d| can_leave(tile, *d)) {
+                if backwards(tiles, *c, *c + d.to_coord()) {
+                    continue;
+                }
+                edges.insert((*c, d), entry_moves(tiles, *c, d));
+            }
+        }
+
+        let mut reverse: BTreeMap<Node, Vec<Node>> = BTreeMap::new();
+        for (node, moves) in &edges {
+            for e in moves {
+                reverse.entry(e.to).or_default().push(*node);
+            }
+        }
+
+        TrackGraph {
+            tiles: tiles.clone(),
+            edges,
+            reverse,
+        }
+    }
+
+    pub fn tiles(&self) -> &BTreeMap<Coord, Tile> {
+        &self.tiles
+    }
+
+    /// Moves on the tile ahead of `from` driving onto it in `direction`, also
+    /// from off the track like behind the grid
+    pub fn entering(&self, from: Coord, direction: Direction) -> Vec<Edge> {
+        entry_moves(&self.tiles, from, direction)
+    }
+
+    /// Turns along the arrows of the tile ahead of `from`, only towards
+    /// `lock` when a branch is picked and the entry is not forced
+    pub fn offered(&self, from: Coord, direction: Direction, lock: Option<Direction>) -> Vec<Turn> {
+        let forced = self.tiles.get(&(from + direction.to_coord())).is_some_and(|t| t.forced.contains_key(&from));
+        self.entering(from, direction)
+            .into_iter()
+            .filter(|e| e.marked)
+            .filter(|e| forced || lock.is_none_or(|l| e.to.1 == l))
+            .map(|e| e.turn)
+            .collect()
+    }
+
+    /// Judge `turn` on the tile ahead of `from` driving onto it in
+    /// `direction`, when the player was offered `offered`
+    pub fn judge(&self, from: Coord, direction: Direction, turn: Turn, offered: &[Turn], too_fast: bool) -> Verdict {
+        let Some(tile) = self.tiles.get(&(from + direction.to_coord())) else {
+            return Verdict::Drive(turn);
+        };
+        if offered.contains(&turn) {
+            return Verdict::Drive(turn);
+        }
+        let legal = self.entering(from, direction).iter().any(|e| e.turn == turn);
+
+        // Must use the offered turns over a choice, forced tiles and when too fast to turn
+        if tile.choice {
+            if !offered.is_empty() && legal {
+                Verdict::WrongBranch(offered[0])
+            } else {
+                Verdict::FallsOff(turn)
+            }
+        } else if !tile.forced.is_empty() || too_fast {
+            match offered.first() {
+                Some(t) => Verdict::Drive(*t),
+                None => Verdict::FallsOff(Turn::Straight),
+            }
+        } else if legal {
+            Verdict::Drive(turn)
+        } else {
+            Verdict::FallsOff(turn)
+        }
+    }
+
+    pub fn contains(&self, node: Node) -> bool {
+        self.edges.contains_key(&node)
+    }
+
+    pub fn moves(&self, node: Node) -> &[Edge] {
+        self.edges.get(&node).map_or(&[], |e| e.as_slice())
+    }
+
+    /// Moves along the arrows only
+    pub fn marked_moves(&self, node: Node) -> impl Iterator<Item = &Edge> {
+        self.moves(node).iter().filter(|e| e.marked)
+    }
+
+    pub fn predecessors(&self, node: Node) -> &[Node] {
+        self.reverse.get(&node).map_or(&[], |p| p.as_slice())
+    }
+
+    /// Nodes reachable from `from` following the arrows
+    pub fn reachable(&self, from: impl IntoIterator<Item = Node>) -> BTreeSet<Node> {
+        let mut visited = BTreeSet::new();
+        let mut stk: Vec<Node> = from.into_iter().filter(|n| self.contains(*n)).collect();
+        while let Some(node) = stk.pop() {
+            if visited.insert(node) {
+                stk.extend(self.marked_moves(node).map(|e| e.to));
+            }
+        }
+        visited
+    }
+}
@@ -0,0 +1,455 @@
+use crate::boards::*;
+use crate::export::*;
+use crate::map::*;
+use crate::util::*;
+use sdl2::pixels::Color;
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::fs;
+use std::path::Path;
+
+// Boards from the Python side, the literals of `maps.py` and pictures of maps
+// as drawn by `draw_hex_map.py`
+
+// Python direction codes, `step_dir` in `run_game.py`
+const PYTHON_DIRECTIONS: [Direction; 6] = [
+    Direction::DR,
+    Direction::D,
+    Direction::DL,
+    Direction::UL,
+    Direction::U,
+    Direction::UR,
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Literal {
+    Int(i32),
+    Seq(Vec<Literal>), // List or tuple
+    Dict(Vec<(Literal, Literal)>),
+    Other, // Names and calls like `dict(player_state_start)`
+}
+
+struct Parser<'a> {
+    source: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn skip(&mut self) {
+        while let Some(c) = self.source.get(self.position) {
+            if *c == b'#' {
+                while self.source.get(self.position).is_some_and(|c| *c != b'\n') {
+                    self.position += 1;
+                }
+            } else if c.is_ascii_whitespace() {
+                self.position += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip();
+        self.source.get(self.position).copied()
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        if self.peek() == Some(c) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(format!("Expected '{}' at byte {}", c as char, self.position))
+        }
+    }
+
+    // Items up to `close`, returns whether they were separated by commas
+    fn items(&mut self, close: u8, dict: bool) -> Result<(Vec<(Literal, Literal)>, bool), String> {
+        let mut items = vec![];
+        let mut comma = false;
+        while self.peek() != Some(close) {
+            let key = self.literal()?;
+            let value = if dict {
+                self.expect(b':')?;
+                self.literal()?
+            } else {
+                Literal::Other
+            };
+            items.push((key, value));
+            if self.peek() == Some(b',') {
+                self.position += 1;
+                comma = true;
+            } else if self.peek() != Some(close) {
+                return Err(format!("Expected '{}' at byte {}", close as char, self.position));
+            }
+        }
+        self.position += 1;
+        Ok((items, comma))
+    }
+
+    fn literal(&mut self) -> Result<Literal, String> {
+        match self.peek() {
+            Some(b'(') => {
+                self.position += 1;
+                let (items, comma) = self.items(b')', false)?;
+                let mut items: Vec<Literal> = items.into_iter().map(|(k, _)| k).collect();
+                if items.len() == 1 && !comma {
+                    Ok(items.remove(0)) // Parenthesized expression
+                } else {
+                    Ok(Literal::Seq(items))
+                }
+            }
+            Some(b'[') => {
+                self.position += 1;
+                let (items, _) = self.items(b']', false)?;
+                Ok(Literal::Seq(items.into_iter().map(|(k, _)| k).collect()))
+            }
+            Some(b'{') => {
+                self.position += 1;
+                Ok(Literal::Dict(self.items(b'}', true)?.0))
+            }
+            Some(c) if c == b'-' || c.is_ascii_digit() => {
+                let start = self.position;
+                self.position += 1;
+                while self.source.get(self.position).is_some_and(|c| c.is_ascii_digit()) {
+                    self.position += 1;
+                }
+                let text = String::from_utf8_lossy(&self.source[start..self.position]);
+                let n: i32 = text.parse().map_err(|_| format!("Invalid number {}", text))?;
+                // Sums like `0-1` used for unset player state
+                match self.peek() {
+                    Some(b'+') => {
+                        self.position += 1;
+                        Ok(Literal::Int(n + int(&self.literal()?)?))
+                    }
+                    Some(b'-') => Ok(Literal::Int(n + int(&self.literal()?)?)),
+                    _ => Ok(Literal::Int(n)),
+                }
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == b'_' => {
+                while self.source.get(self.position).is_some_and(|c| c.is_ascii_alphanumeric() || *c == b'_') {
+                    self.position += 1;
+                }
+                if self.peek() == Some(b'(') {
+                    self.literal()?; // Arguments of a call
+                }
+                Ok(Literal::Other)
+            }
+            c => Err(format!("Unexpected {:?} at byte {}", c.map(|c| c as char), self.position)),
+        }
+    }
+}
+
+fn int(l: &Literal) -> Result<i32, String> {
+    match l {
+        Literal::Int(i) => Ok(*i),
+        _ => Err(format!("Expected a number, found {:?}", l)),
+    }
+}
+
+fn seq(l: &Literal) -> Result<&Vec<Literal>, String> {
+    match l {
+        Literal::Seq(items) => Ok(items),
+        _ => Err(format!("Expected a list, found {:?}", l)),
+    }
+}
+
+fn coord(l: &Literal) -> Result<Coord, String> {
+    match seq(l)?.as_slice() {
+        [x, y] => Ok(Coord::axel(int(x)?, int(y)?)),
+        _ => Err(format!("Expected a coordinate, found {:?}", l)),
+    }
+}
+
+fn direction(l: &Literal) -> Result<Direction, String> {
+    let d = int(l)?;
+    PYTHON_DIRECTIONS
+        .get(d as usize)
+        .copied()
+        .ok_or_else(|| format!("Invalid direction {}", d))
+}
+
+/// Names of the map functions in a `maps.py` file
+pub fn python_maps(source: &str) -> Vec<String> {
+    source
+        .lines()
+        .filter_map(|l| l.strip_prefix("def "))
+        .filter_map(|l| l.split('(').next())
+        .map(|name| name.trim().to_string())
+        .collect()
+}
+
+// Value assigned to `name` in the body of the function
+fn assignment(body: &str, name: &str) -> Result<Option<Literal>, String> {
+    for line in body.lines() {
+        let Some((left, _)) = line.split_once('=') else {
+            continue;
+        };
+        if left.trim() != name {
+            continue;
+        }
+        let offset = line.as_ptr() as usize - body.as_ptr() as usize + left.len() + 1;
+        let mut parser = Parser {
+            source: body.as_bytes(),
+            position: offset,
+        };
+        return parser.literal().map(Some);
+    }
+    Ok(None)
+}
+
+// Forced tiles send players on depending on where they came from: straight
+// through when possible, otherwise the direction reachable with a turn
+fn forced_directions(tiles: &BTreeMap<Coord, Tile>, c: Coord) -> BTreeMap<Coord, Direction> {
+    let mut forced = BTreeMap::new();
+    for (p, t) in tiles {
+        for d in &t.directions {
+            if *p + d.to_coord() != c || *p == c {
+                continue;
+            }
+            let outgoing = &tiles[&c].directions;
+            if let Some(out) = outgoing
+                .iter()
+                .find(|o| *o == d)
+                .or_else(|| outgoing.iter().find(|o| d.turn_to_dir(**o).is_some()))
+            {
+                forced.insert(*p, *out);
+            }
+        }
+    }
+    forced
+}
+
+/// The map returned by `function` in the Python source, with tiles
+/// `(x, y): ([directions], [types])` and types
+/// 0 standard, 1 start, 2 blue, 3 star (chikane), 4 choice direction, 5 forced dirs
+pub fn python_board(source: &str, function: &str) -> Result<HexMap, String> {
+    let header = format!("def {}(", function);
+    let start = source
+        .find(&header)
+        .ok_or_else(|| format!("No map {}, found {}", function, python_maps(source).join(", ")))?;
+    let body = &source[start + header.len()..];
+    let body = &body[..body.find("\ndef ").unwrap_or(body.len())];
+
+    let Some(Literal::Dict(entries)) = assignment(body, "game_map")? else {
+        return Err("No game_map".to_string());
+    };
+
+    let mut tiles = BTreeMap::new();
+    let mut forced = vec![];
+    for (key, value) in &entries {
+        let c = coord(key)?;
+        let (directions, types) = match seq(value)?.as_slice() {
+            [d, t] => (seq(d)?, seq(t)?),
+            _ => return Err(format!("Invalid tile at {:?}", c)),
+        };
+        if directions.is_empty() {
+            continue; // Leads nowhere, the same as off the board
+        }
+
+        let mut tile = Tile::new(directions.iter().map(direction).collect::<Result<_, _>>()?);
+        for t in types {
+            match int(t)? {
+                1 => tile = tile.start(),
+                2 => tile = tile.blue(),
+                3 => tile = tile.chikane(),
+                4 => tile = tile.choice(),
+                5 => forced.push(c),
+                _ => {}
+            }
+        }
+        tiles.insert(c, tile);
+    }
+    for c in forced {
+        let directions = forced_directions(&tiles, c);
+        let tile = tiles.remove(&c).unwrap();
+        tiles.insert(c, tile.forced(directions));
+    }
+
+    let line = |name: &str| -> Result<Vec<(Coord, Vec<Direction>)>, String> {
+        match assignment(body, name)? {
+            Some(l) => seq(&l)?
+                .iter()
+                .map(|c| {
+                    let c = coord(c)?;
+                    let directions = tiles.get(&c).map(|t| t.directions.clone()).unwrap_or_default();
+                    Ok((c, directions))
+                })
+                .collect(),
+            None => Ok(vec![]),
+        }
+    };
+    let start_line = line("start_line")?;
+    let mid_line = line("mid_point")?;
+
+    let placements: Vec<(Coord, Direction)> = match assignment(body, "players")? {
+        Some(l) => seq(&l)?
+            .iter()
+            .map(|p| match seq(p)?.as_slice() {
+                [c, d, ..] => Ok((coord(c)?, direction(d)?)),
+                _ => Err(format!("Invalid player {:?}", p)),
+            })
+            .collect::<Result<_, String>>()?,
+        None => vec![],
+    };
+
+    let mut map = HexMap {
+        tiles,
+        start_line,
+        mid_line,
+        player_builder: PlayerBuilder::new(placements.len(), player_colors(), placements),
+    };
+    // Several of the Python maps never got a mid point
+    if map.mid_line.is_empty() {
+        map.mid_line = map.detect_mid_line();
+    }
+    Ok(map)
+}
+
+/// Placement of the hex grid in a picture of a map
+#[derive(Clone, Copy, Debug)]
+pub struct ImageGrid {
+    pub scale: f64,         // Pixels from the center of a hex to a corner
+    pub origin: (f64, f64), // Pixel at the center of hex (0, 0)
+}
+
+// Hue in degrees, saturation and value in [0, 1]
+fn hsv(c: Color) -> (f64, f64, f64) {
+    let (r, g, b) = (c.r as f64 / 255.0, c.g as f64 / 255.0, c.b as f64 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    (hue, if max == 0.0 { 0.0 } else { delta / max }, max)
+}
+
+/// Tiles from the colors of a picture: yellow road, blue tiles, darker start
+/// fields, a red center for chikanes, and direction marks from the center
+/// towards each side, white for plain, grey for choice and black for forced
+/// directions. The start line, mid line and grid are left empty.
+pub fn image_board(image: &Image, grid: &ImageGrid) -> HexMap {
+    let at = |c: Coord, angle: f64, distance: f64| {
+        let x = grid.origin.0 + grid.scale * 1.5 * c.q as f64 + grid.scale * distance * angle.cos();
+        // Python draws with y upwards, so the picture is upside down
+        let y = grid.origin.1 - grid.scale * 3_f64.sqrt() * (c.r as f64 + c.q as f64 / 2.0) - grid.scale * distance * angle.sin();
+        image.pixel(x.round() as i32, y.round() as i32)
+    };
+
+    let q_range = (image.width as f64 / (grid.scale * 1.5)).ceil() as i32 + 1;
+    let r_range = (image.height as f64 / (grid.scale * 3_f64.sqrt())).ceil() as i32 + q_range;
+
+    let mut tiles = BTreeMap::new();
+    let mut forced = vec![];
+    for q in -q_range..=q_range {
+        for r in -r_range..=r_range {
+            let c = Coord::axel(q, r);
+            // Sample the fill towards the corners, away from the direction marks
+            let Some((hue, saturation, value)) = [0.0, PI, PI / 3.0]
+                .iter()
+                .filter_map(|a| at(c, *a, 0.7))
+                .map(hsv)
+                .find(|(_, s, v)| *s > 0.3 && *v > 0.2)
+            else {
+                continue;
+            };
+            if saturation <= 0.3 {
+                continue;
+            }
+
+            let mut tile = Tile::default();
+            if (190.0..=250.0).contains(&hue) {
+                tile = tile.blue();
+            } else if !(35.0..=75.0).contains(&hue) {
+                continue; // Not a tile color
+            }
+            if value < 0.62 {
+                tile = tile.start();
+            }
+            // The red star, between the direction marks crossing the center
+            let red = |(h, s, _): (f64, f64, f64)| !(15.0..=345.0).contains(&h) && s > 0.5;
+            if (0..6).filter_map(|k| at(c, k as f64 * PI / 3.0, 0.2)).map(hsv).filter(|p| red(*p)).count() >= 3 {
+                tile = tile.chikane();
+            }
+
+            let mut choice = false;
+            let mut is_forced = false;
+            for d in 0..6 {
+                let d = Direction::from_num(d);
+                // Any mark near the ray, the lines are thin
+                let mark = [0.0, 0.06, -0.06]
+                    .iter()
+                    .filter_map(|o| at(c, d.angle() + o, 0.45))
+                    .map(hsv)
+                    .find(|(_, s, _)| *s < 0.2);
+                let Some((_, _, v)) = mark else {
+                    continue;
+                };
+                tile.directions.push(d);
+                if v < 0.2 {
+                    is_forced = true;
+                } else if v < 0.85 {
+                    choice = true;
+                }
+            }
+            if tile.directions.is_empty() {
+                continue;
+            }
+            if choice {
+                tile = tile.choice();
+            }
+            if is_forced {
+                forced.push(c);
+            }
+            tiles.insert(c, tile);
+        }
+    }
+    for c in forced {
+        let directions = forced_directions(&tiles, c);
+        let tile = tiles.remove(&c).unwrap();
+        tiles.insert(c, tile.forced(directions));
+    }
+
+    let mut map = HexMap {
+        tiles,
+        start_line: vec![],
+        mid_line: vec![],
+        player_builder: PlayerBuilder::new(0, player_colors(), vec![]),
+    };
+    map.detect_lines();
+    map
+}
+
+/// Board named `<maps.py>:<function>` or `<image.png>:<scale>,<x>,<y>`,
+/// `None` when the name is not a path
+pub fn import_board(name: &str) -> Result<Option<HexMap>, String> {
+    let Some((path, detail)) = name.rsplit_once(':') else {
+        return Ok(None);
+    };
+    let path = Path::new(path);
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("py") => {
+            let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+            python_board(&source, detail).map(Some)
+        }
+        Some("png") => {
+            let numbers: Vec<f64> = detail
+                .split(',')
+                .map(|n| n.parse::<f64>().map_err(|_| format!("Invalid number {}", n)))
+                .collect::<Result<_, _>>()?;
+            let [scale, x, y] = numbers[..] else {
+                return Err("Expected <scale>,<x>,<y>".to_string());
+            };
+            let image = Image::load_png(path).map_err(|e| e.to_string())?;
+            Ok(Some(image_board(&image, &ImageGrid { scale, origin: (x, y) })))
+        }
+        _ => Ok(None),
+    }
+}
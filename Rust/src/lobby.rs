@@ -0,0 +1,232 @@
+use crate::boards::*;
+use crate::canvas_draw::*;
+use crate::map::*;
+use sdl2::EventPump;
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, TextInputUtil};
+use sdl2::pixels::Color;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use std::time::Duration;
+
+// Hot-seat play in the window: before the race every seat is given to a
+// person at the keyboard or to a computer player, along with a name and a
+// color. Human turns wait for the keys of the manual strategies, computer
+// turns are animated one step at a time at the speed chosen here.
+
+/// Who can drive the car of a seat
+pub const DRIVERS: [(&str, PlayerGearStrategy, PlayerStepStrategy); 3] = [
+    ("Human", PlayerGearStrategy::Manual, PlayerStepStrategy::Manual),
+    ("Best", PlayerGearStrategy::Best, PlayerStepStrategy::Best),
+    ("Optimal", PlayerGearStrategy::Optimal, PlayerStepStrategy::Optimal),
+];
+
+/// Pause after each step of a computer player, `None` simulates races without
+/// pausing as `GameState::simulate` does, only offered without humans
+pub const SPEEDS: [(&str, Option<u64>); 5] = [
+    ("Slow", Some(500)),
+    ("Normal", Some(150)),
+    ("Fast", Some(40)),
+    ("Instant", Some(0)),
+    ("Simulate", None),
+];
+
+const NAME_LENGTH: usize = 12;
+const TEXT_SIZE: f64 = 24.0;
+const LINE: i32 = 40;
+
+#[derive(Clone, Debug)]
+pub struct Seat {
+    pub name: String,
+    pub color: Color,
+    pub driver: usize, // Index in `DRIVERS`
+}
+
+impl Seat {
+    pub fn strategy(&self) -> (PlayerGearStrategy, PlayerStepStrategy) {
+        let (_, gear, step) = DRIVERS[self.driver];
+        (gear, step)
+    }
+
+    pub fn human(&self) -> bool {
+        self.driver == 0
+    }
+}
+
+/// Seats of the players of a board, one for each entrant
+#[derive(Clone, Debug)]
+pub struct Lobby {
+    pub seats: Vec<Seat>,
+    pub speed: usize, // Index in `SPEEDS`
+    selected: usize,  // Seat being edited, the speed after the last seat
+}
+
+impl Lobby {
+    /// Seats for the players of `map`, driven by `strategies` in turn order
+    /// where they name a driver, by the best strategy otherwise
+    pub fn new(map: &HexMap, strategies: &[(PlayerGearStrategy, PlayerStepStrategy)]) -> Self {
+        let builder = &map.player_builder;
+        let seats = (0..builder.total)
+            .map(|entrant| Seat {
+                name: format!("Player {}", entrant + 1),
+                color: builder.colors[entrant % builder.colors.len()],
+                driver: (0..builder.total)
+                    .find(|i| builder.entrant(*i) == entrant)
+                    .and_then(|i| strategies.get(i))
+                    .and_then(|(g, s)| DRIVERS.iter().position(|(_, dg, ds)| dg == g && ds == s))
+                    .unwrap_or(1),
+            })
+            .collect();
+        Lobby { seats, speed: 1, selected: 0 }
+    }
+
+    /// Let the players take their seats, `None` when the window is closed
+    pub fn run(mut self, canvas: &mut Canvas<Window>, event_pump: &mut EventPump, text_input: &TextInputUtil) -> Option<Self> {
+        text_input.start();
+        let lobby = loop {
+            match self.poll(event_pump) {
+                Some(true) => break Some(self),
+                Some(false) => break None,
+                None => {}
+            }
+            canvas.set_draw_color(Color::RGB(0, 0, 0));
+            canvas.clear();
+            self.draw(canvas);
+            canvas.present();
+            ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+        };
+        text_input.stop();
+        lobby
+    }
+
+    // Edit the seats by keyboard, `Some(true)` to start the race and `Some(false)` to quit
+    fn poll(&mut self, event_pump: &mut EventPump) -> Option<bool> {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => return Some(false),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Return | Keycode::KP_ENTER),
+                    ..
+                } => return Some(true),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Up),
+                    ..
+                } => self.selected = self.selected.saturating_sub(1),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Down),
+                    ..
+                } => self.selected = (self.selected + 1).min(self.seats.len()),
+                Event::KeyDown {
+                    keycode: Some(key @ (Keycode::Left | Keycode::Right)),
+                    ..
+                } => self.change(key == Keycode::Right),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } => self.next_color(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => {
+                    if let Some(seat) = self.seats.get_mut(self.selected) {
+                        seat.name.pop();
+                    }
+                }
+                Event::TextInput { text, .. } => {
+                    if let Some(seat) = self.seats.get_mut(self.selected) {
+                        for c in text.chars().filter(|c| c.is_ascii_alphanumeric() || *c == ' ') {
+                            if seat.name.len() < NAME_LENGTH {
+                                seat.name.push(c);
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    // Next or previous driver of the selected seat, or speed
+    fn change(&mut self, forward: bool) {
+        let step = |i: usize, n: usize| if forward { (i + 1) % n } else { (i + n - 1) % n };
+        if let Some(seat) = self.seats.get_mut(self.selected) {
+            seat.driver = step(seat.driver, DRIVERS.len());
+            if self.humans() && SPEEDS[self.speed].1.is_none() {
+                self.speed = SPEEDS.len() - 2; // Instant
+            }
+        } else {
+            let speeds = if self.humans() { SPEEDS.len() - 1 } else { SPEEDS.len() };
+            self.speed = step(self.speed.min(speeds - 1), speeds);
+        }
+    }
+
+    // Next color of the board nobody else has taken
+    fn next_color(&mut self) {
+        let Some(seat) = self.seats.get(self.selected) else {
+            return;
+        };
+        let colors = player_colors();
+        let current = colors.iter().position(|c| *c == seat.color).unwrap_or(0);
+        let free = (1..colors.len())
+            .map(|i| colors[(current + i) % colors.len()])
+            .find(|c| self.seats.iter().all(|s| s.color != *c));
+        if let Some(color) = free {
+            self.seats[self.selected].color = color;
+        }
+    }
+
+    pub fn humans(&self) -> bool {
+        self.seats.iter().any(Seat::human)
+    }
+
+    /// Pause after each step of a computer player, none when simulating
+    pub fn step_delay(&self) -> Option<Duration> {
+        SPEEDS[self.speed].1.map(Duration::from_millis)
+    }
+
+    /// Put the seats on the players of `map`, returning their strategies and
+    /// names in turn order
+    pub fn apply(&self, map: &mut HexMap) -> (Vec<(PlayerGearStrategy, PlayerStepStrategy)>, Vec<String>) {
+        map.player_builder.colors = self.seats.iter().map(|s| s.color).collect();
+        (0..self.seats.len())
+            .map(|i| {
+                let seat = &self.seats[map.player_builder.entrant(i)];
+                (seat.strategy(), seat.name.clone())
+            })
+            .unzip()
+    }
+
+    fn draw(&self, canvas: &mut Canvas<Window>) {
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        draw_text(canvas, "Racebile", LINE, LINE, TEXT_SIZE * 1.5);
+
+        for (i, seat) in self.seats.iter().enumerate() {
+            let y = LINE * (i as i32 + 3);
+            canvas.set_draw_color(seat.color);
+            canvas.fill_hexagon(LINE + LINE / 2, y + LINE / 3, TEXT_SIZE / 2.0);
+            draw_text(canvas, &seat.name, LINE * 3, y, TEXT_SIZE);
+            draw_text(canvas, DRIVERS[seat.driver].0, LINE * 12, y, TEXT_SIZE);
+        }
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        let y = LINE * (self.seats.len() as i32 + 3);
+        draw_text(canvas, "Speed", LINE * 3, y, TEXT_SIZE);
+        draw_text(canvas, SPEEDS[self.speed].0, LINE * 12, y, TEXT_SIZE);
+
+        draw_text(canvas, ">", LINE * 2, LINE * (self.selected as i32 + 3), TEXT_SIZE);
+
+        let help = [
+            "Up/Down: Seat or speed",
+            "Left/Right: Driver or speed",
+            "Tab: Color  Type: Name",
+            "Enter: Start  Escape: Quit",
+        ];
+        for (i, line) in help.iter().enumerate() {
+            draw_text(canvas, line, LINE, LINE * (self.seats.len() as i32 + 5 + i as i32), TEXT_SIZE * 0.75);
+        }
+    }
+}
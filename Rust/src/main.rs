@@ -4,12 +4,290 @@ pub mod util;
 pub mod canvas_draw;
 pub mod player;
 pub mod boards;
+pub mod env;
+pub mod solver;
+pub mod simulation;
+pub mod analysis;
+pub mod overlay;
+pub mod export;
+pub mod replay;
+pub mod print;
+pub mod importer;
+pub mod generator;
+pub mod graph;
+pub mod pathfinding;
+pub mod rules;
+pub mod chikane;
+pub mod choice;
+pub mod effects;
+pub mod session;
+pub mod qualifying;
+pub mod team;
+pub mod lobby;
 
 use crate::map::*;
 use crate::util::*;
 use crate::boards::*;
+use crate::rules::*;
+use std::sync::Arc;
 
 fn main() {
+    let mut args: Vec<String> = std::env::args().collect();
+
+    // racebile [--rules <babyracerbile|racerbile|lemans|rules.txt>] ...
+    let rules = match args.iter().position(|a| a == "--rules") {
+        Some(i) if i + 1 < args.len() => {
+            let spec = args.remove(i + 1);
+            args.remove(i);
+            match RuleSet::find(&spec) {
+                Ok(rules) => rules,
+                Err(e) => {
+                    eprintln!("Could not load rules {}: {}", spec, e);
+                    return;
+                }
+            }
+        }
+        Some(_) => {
+            eprintln!("Missing rule set after --rules");
+            return;
+        }
+        None => RuleSet::default(),
+    };
+    let rules = Arc::new(rules);
+
+    // racebile [--qualifying <rolloff|timetrial|time,time,...>] ...
+    let qualifying = match args.iter().position(|a| a == "--qualifying") {
+        Some(i) if i + 1 < args.len() => {
+            let spec = args.remove(i + 1);
+            args.remove(i);
+            let Some(qualifying) = crate::qualifying::Qualifying::parse(&spec) else {
+                eprintln!("Unknown qualifying {}", spec);
+                return;
+            };
+            Some(qualifying)
+        }
+        Some(_) => {
+            eprintln!("Missing qualifying after --qualifying");
+            return;
+        }
+        None => None,
+    };
+    let qualifying_seed: u64 = rand::random();
+
+    // The board with the rules and the grid after qualifying, and the
+    // `strategies` of its players put in turn order
+    let with_rules = |mut board: HexMap, mut strategies: Vec<(PlayerGearStrategy, PlayerStepStrategy)>| {
+        board.player_builder.rules = rules.clone();
+        if let Some(qualifying) = &qualifying {
+            let ranking = qualifying.ranking(&board, &strategies, qualifying_seed);
+            let entrants: Vec<String> = ranking.iter().map(|i| (i + 1).to_string()).collect();
+            eprintln!("Grid: {}", entrants.join(", "));
+            crate::qualifying::apply(ranking, &mut board, &mut strategies);
+        }
+        (board, strategies)
+    };
+    let board = |name: &str| {
+        board(name).map(|board| {
+            let strategies = vec![(PlayerGearStrategy::Best, PlayerStepStrategy::Best); board.player_builder.total];
+            with_rules(board, strategies)
+        })
+    };
+
+    // racebile rules
+    if args.get(1).map(String::as_str) == Some("rules") {
+        if let Err(e) = rules.write(std::io::stdout()) {
+            eprintln!("Could not write rules: {}", e);
+        }
+        return;
+    }
+
+    // racebile analyze [board ...]
+    if args.get(1).map(String::as_str) == Some("analyze") {
+        let names: Vec<&str> = if args.len() > 2 {
+            args[2..].iter().map(String::as_str).collect()
+        } else {
+            BOARDS.iter().map(|(name, _)| *name).collect()
+        };
+        for name in names {
+            let Some((board, _)) = board(name) else {
+                eprintln!("Unknown board {}", name);
+                continue;
+            };
+            println!("{}", crate::analysis::BoardReport::new(name, &board, 200));
+        }
+        return;
+    }
+
+    // racebile export <board> <file.png|file.svg> [seed turns]
+    if args.get(1).map(String::as_str) == Some("export") {
+        if args.len() != 4 && args.len() != 6 {
+            eprintln!("Usage: {} export <board> <file.png|file.svg> [seed turns]", args[0]);
+            return;
+        }
+        let Some((board, strategies)) = board(&args[2]) else {
+            eprintln!("Unknown board {}", args[2]);
+            return;
+        };
+        let path = std::path::Path::new(&args[3]);
+
+        let result = if args.len() == 6 {
+            let (Ok(seed), Ok(turns)) = (args[4].parse::<u64>(), args[5].parse::<usize>()) else {
+                eprintln!("Seed and turns must be numbers");
+                return;
+            };
+            set_verbose(false);
+            let mut game = GameState::headless(board, strategies, seed);
+            let mut played = 0;
+            while played < turns {
+                if game.rolling {
+                    played += 1;
+                }
+                game.step_game(None);
+            }
+            while !game.rolling {
+                game.step_game(None); // Finish the last turn
+            }
+            game.export(path, crate::export::EXPORT_SCALE)
+        } else {
+            crate::export::export(path, &board, &[], &[], crate::export::EXPORT_SCALE)
+        };
+        if let Err(e) = result {
+            eprintln!("Could not export {}: {}", args[3], e);
+        }
+        return;
+    }
+
+    // racebile record <board> <seed> <replay.txt>
+    // racebile animate <board> <seed> <file.gif|directory>
+    // racebile animate <replay.txt> <file.gif|directory>
+    if let Some(command @ ("record" | "animate")) = args.get(1).map(String::as_str) {
+        let (replay, output) = match args.len() {
+            5 => {
+                let Some((board, _)) = board(&args[2]) else {
+                    eprintln!("Unknown board {}", args[2]);
+                    return;
+                };
+                let Ok(seed) = args[3].parse::<u64>() else {
+                    eprintln!("Seed must be a number");
+                    return;
+                };
+                (crate::replay::Replay::race(&args[2], board, seed, 1, 200), &args[4])
+            }
+            4 if command == "animate" => match crate::replay::Replay::load(std::path::Path::new(&args[2])) {
+                Ok(replay) => (replay, &args[3]),
+                Err(e) => {
+                    eprintln!("Could not read {}: {}", args[2], e);
+                    return;
+                }
+            },
+            _ => {
+                eprintln!("Usage: {} record <board> <seed> <replay.txt>", args[0]);
+                eprintln!("       {} animate (<board> <seed> | <replay.txt>) <file.gif|directory>", args[0]);
+                return;
+            }
+        };
+
+        let path = std::path::Path::new(output);
+        let result = if command == "record" {
+            replay.save(path)
+        } else {
+            match board(&replay.board) {
+                Some((map, _)) => crate::export::animate(path, &map, &replay, crate::export::EXPORT_SCALE),
+                None => Err(std::io::Error::other(format!("Unknown board {}", replay.board))),
+            }
+        };
+        if let Err(e) = result {
+            eprintln!("Could not write {}: {}", output, e);
+        }
+        return;
+    }
+
+    // racebile solve <board> <gears.csv> [turns.csv]
+    if args.get(1).map(String::as_str) == Some("solve") {
+        if args.len() != 4 && args.len() != 5 {
+            eprintln!("Usage: {} solve <board> <gears.csv> [turns.csv]", args[0]);
+            return;
+        }
+        let Some((board, _)) = board(&args[2]) else {
+            eprintln!("Unknown board {}", args[2]);
+            return;
+        };
+        set_verbose(false);
+        let solver = crate::solver::Solver::solve(board);
+        let mut tables = vec![(&args[3], solver.gear_table())];
+        if let Some(path) = args.get(4) {
+            tables.push((path, solver.turn_table()));
+        }
+        for (path, table) in tables {
+            if let Err(e) = std::fs::write(path, table) {
+                eprintln!("Could not write {}: {}", path, e);
+                return;
+            }
+        }
+        return;
+    }
+
+    // racebile lemans <board> <seed> <rounds|minutes m>
+    if args.get(1).map(String::as_str) == Some("lemans") {
+        if args.len() != 5 {
+            eprintln!("Usage: {} lemans <board> <seed> <rounds|minutes m>", args[0]);
+            return;
+        }
+        let Some((board, strategies)) = board(&args[2]) else {
+            eprintln!("Unknown board {}", args[2]);
+            return;
+        };
+        let Ok(seed) = args[3].parse::<u64>() else {
+            eprintln!("Seed must be a number");
+            return;
+        };
+        let Some(clock) = crate::session::SessionClock::parse(&args[4]) else {
+            eprintln!("Session must be a number of rounds or minutes like 90m");
+            return;
+        };
+        if !board.tiles.values().any(Tile::is_pit) {
+            eprintln!("{} has no pit lane, try generated:<seed>,pit=<tiles>", args[2]);
+            return;
+        }
+        set_verbose(false);
+        let mut race = crate::team::LeMansRace::new(board, strategies, seed);
+        race.run(clock);
+        print!("{}", race);
+        return;
+    }
+
+    // racebile print <board> <prefix> [a4|letter] [hex mm]
+    if args.get(1).map(String::as_str) == Some("print") {
+        if args.len() < 4 || args.len() > 6 {
+            eprintln!("Usage: {} print <board> <prefix> [a4|letter] [hex mm]", args[0]);
+            return;
+        }
+        let Some((board, _)) = board(&args[2]) else {
+            eprintln!("Unknown board {}", args[2]);
+            return;
+        };
+        let mut layout = crate::print::PrintLayout::default();
+        if let Some(paper) = args.get(4) {
+            let Some(paper) = crate::print::Paper::from_name(paper) else {
+                eprintln!("Unknown paper {}", paper);
+                return;
+            };
+            layout.paper = paper;
+        }
+        if let Some(hex) = args.get(5) {
+            let Ok(hex) = hex.parse::<f64>() else {
+                eprintln!("Hex size must be a number");
+                return;
+            };
+            layout.hex_mm = hex;
+        }
+        match crate::print::save_pages(&args[3], &args[2], &board, &layout) {
+            Ok(pages) => println!("Wrote {} pages", pages),
+            Err(e) => eprintln!("Could not write pages: {}", e),
+        }
+        return;
+    }
+
     // let board = rtfm();
     // let scale: f64 = 42.0;
     // let start = Coord::new(360, 700);
@@ -30,9 +308,9 @@ fn main() {
     use crate::map::{PlayerGearStrategy as PGS};
     use crate::map::{PlayerStepStrategy as PSS};
 
-    GameState::new(board, vec![
+    // Drivers the seats start with, changed in the lobby before the race
+    let (board, strategies) = with_rules(board, vec![
         (PGS::Best, PSS::Best),
-        // (PGS::Manual, PSS::Manual),
         (PGS::Best, PSS::Best),
         (PGS::Best, PSS::Best),
         (PGS::Best, PSS::Best),
@@ -41,5 +319,6 @@ fn main() {
         (PGS::Best, PSS::Best),
         (PGS::Best, PSS::Best),
         (PGS::Best, PSS::Best),
-    ], scale, start); // .display();
+    ]);
+    GameState::new(board, strategies, scale, start); // .display();
 }
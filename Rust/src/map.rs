@@ -1,15 +1,32 @@
 use crate::canvas_draw::*;
+use crate::choice::*;
+use crate::chikane::{self, ChikaneEvent};
+use crate::export::*;
+use crate::graph::*;
+use crate::lobby::*;
+use crate::overlay::*;
+use crate::pathfinding::*;
 use crate::player::*;
+use crate::rules::*;
+use crate::session::*;
+use crate::simulation::*;
+use crate::solver::*;
+use crate::trace;
 use crate::util::*;
+use rand::rngs::StdRng;
 use rand::seq::IndexedRandom;
 use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::Color;
 use sdl2::render::Canvas;
 use sdl2::video::Window;
 use sdl2::*;
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
 
 #[derive(Clone)]
@@ -17,7 +34,9 @@ pub struct PlayerBuilder {
     pub total: usize,
     pub index: usize,
     pub colors: Vec<Color>,
-    pub placements: Vec<(Coord, Direction)>,
+    pub placements: Vec<(Coord, Direction)>, // Grid slots, pole position first
+    pub order: Vec<usize>, // Players in turn order and grid slot after qualifying, by index when empty
+    pub rules: Arc<RuleSet>,
 }
 
 impl PlayerBuilder {
@@ -31,11 +50,18 @@ impl PlayerBuilder {
             total,
             colors,
             placements,
+            order: vec![],
+            rules: Arc::new(RuleSet::default()),
         }
     }
 
+    /// Player taking turn and grid slot `index`
+    pub fn entrant(&self, index: usize) -> usize {
+        self.order.get(index).copied().unwrap_or(index)
+    }
+
     pub fn next_player(&mut self) -> Player {
-        let color = self.colors[self.index % self.colors.len()];
+        let color = self.colors[self.entrant(self.index) % self.colors.len()];
         let radius = 0.2 + (0.9 - 0.2) * (self.index as f64) / (self.total as f64);
 
         let (position, direction) = self.placements[self.index];
@@ -48,11 +74,13 @@ impl PlayerBuilder {
             direction,
             color,
             radius,
-            outside_board: false,
+            crash: None,
+            wrong_branch: None,
             gear: 1,
             stalled: true,
             roll: vec![], // Set by roll_dice
             steps: 0,     // Set by roll_dice
+            boost: 0,     // Set by roll_dice
             bonked: false,
             finished: false,
             forced_gear_down: false,
@@ -60,6 +88,12 @@ impl PlayerBuilder {
             round: 1,
             first_half: true,
             turned_over: false,
+            sips: Sips::default(),
+            rules: self.rules.clone(),
+            oriented: false,
+            on_grid: self.rules.variant == Variant::Racerbile,
+            whines: 0,
+            on_two_wheels: false,
         }
     }
 
@@ -79,99 +113,148 @@ pub struct HexMap {
 }
 
 impl HexMap {
-    pub fn shortest_path(
-        &mut self,
+    pub fn graph(&self) -> TrackGraph {
+        TrackGraph::new(&self.tiles)
+    }
+
+    /// Maps leaving a tile in a direction to the number of further steps
+    /// needed to get past `line`
+    pub fn distance_map(
+        &self,
         line: &Vec<(Coord, Vec<Direction>)>,
-    ) -> BTreeMap<Coord, Vec<Direction>> {
-        let mut shortest_dist_map: BTreeMap<Coord, Vec<Direction>> = BTreeMap::new();
-
-        let mut visit_order = vec![];
-
-        let mut stk = vec![];
-        {
-            for (c, dirs) in line {
-                for d in dirs {
-                    for new_d in &self.tiles[&(*c + d.to_coord())].directions {
-                        // Take a step
-                        stk.push(((*c + d.to_coord(), *new_d),-1));
-                    }
-                }
-            }
-        }
+    ) -> BTreeMap<(Coord, Direction), usize> {
+        DistanceField::new(&self.graph(), &self.tiles, line, &Obstacles::default()).distances
+    }
 
-        let mut visited: BTreeSet<(Coord, Direction)> = BTreeSet::new();
-        let mut index = 0;
-        while index < stk.len() {
-            let ((c, d), bredth) = stk[index];
-            index += 1;
+    /// Tiles just past `line` and the direction they are entered in
+    pub fn line_exits(&self, line: &[(Coord, Vec<Direction>)]) -> Vec<(Coord, Direction)> {
+        line.iter()
+            .flat_map(|(c, dirs)| dirs.iter().map(|d| (*c + d.to_coord(), *d)))
+            .collect()
+    }
 
-            if visited.contains(&(c, d)) {
-                continue;
-            }
-            if bredth != -1 {
-                visited.insert((c, d));
+    /// Moves from a start field onto the rest of the track, in about the
+    /// direction most of the grid is facing
+    pub fn detect_start_line(&self) -> Vec<(Coord, Vec<Direction>)> {
+        let grid = self.tiles.iter().filter(|(_, t)| t.start_field);
+        let mut facing: BTreeMap<Direction, usize> = BTreeMap::new();
+        for (_, t) in grid.clone() {
+            for d in &t.directions {
+                *facing.entry(*d).or_default() += 1;
             }
-            visit_order.push(((c, d), bredth));
+        }
+        let Some(heading) = facing.iter().max_by_key(|(_, n)| **n).map(|(d, _)| *d) else {
+            return vec![];
+        };
+
+        grid.filter_map(|(c, t)| {
+            let dirs: Vec<Direction> = t
+                .directions
+                .iter()
+                .filter(|d| [Turn::Straight, Turn::Left, Turn::Right].iter().any(|t| heading + *t == **d))
+                .filter(|d| self.tiles.get(&(*c + d.to_coord())).is_some_and(|n| !n.start_field))
+                .copied()
+                .collect();
+            (!dirs.is_empty()).then_some((*c, dirs))
+        })
+        .collect()
+    }
 
-            // Fell outside map (TODO: Allow this to reset direction!)
-            if !self.tiles.contains_key(&c) {
+    /// Moves halfway around the lap along the tile directions, counted on the
+    /// shortest way from the start line back to it
+    pub fn detect_mid_line(&self) -> Vec<(Coord, Vec<Direction>)> {
+        let graph = self.graph();
+        let mut steps: BTreeMap<Coord, usize> = BTreeMap::new();
+        let mut visited: BTreeSet<Node> = BTreeSet::new();
+        let mut queue: VecDeque<(Node, usize)> = self
+            .start_line
+            .iter()
+            .flat_map(|(c, dirs)| dirs.iter().map(|d| (*c, *d)))
+            .flat_map(|node| graph.marked_moves(node).map(|e| (e.to, 0)))
+            .collect();
+        while let Some((node, n)) = queue.pop_front() {
+            if !visited.insert(node) {
                 continue;
             }
+            steps.entry(node.0).or_insert(n);
+            queue.extend(graph.marked_moves(node).map(|e| (e.to, n + 1)));
+        }
 
-            for t in [Turn::Straight, Turn::Left, Turn::Right] {
-                let new_d = d + t;
-                let new_c = c - new_d.to_coord();
-
-                // Backwards?
-                if self.tiles.contains_key(&new_c) && self.tiles[&new_c].blockage.contains(&new_d) {
-                    continue;
-                }
-
-                if self.tiles.contains_key(&new_c)
-                    && self.tiles[&new_c].choice
-                    && !self.tiles[&new_c].directions.contains(&new_d)
-                {
-                    continue;
-                }
-
-                if self.tiles.contains_key(&new_c) && self.tiles[&new_c].oneway {
-                    if !self.tiles[&new_c].directions.contains(&new_d) {
-                        continue;
-                    }
-                }
-
-                if self.tiles.contains_key(&new_c)
-                    && self.tiles[&new_c].forced.contains_key(&c)
-                    && !self.tiles[&c].forced.contains_key(&new_c)
-                {
-                    continue;
-                }
+        let lap = self.start_line.iter().filter_map(|(c, _)| steps.get(c)).min();
+        let Some(lap) = lap.map(|n| n + 1) else {
+            return vec![];
+        };
+        let half = lap.div_ceil(2);
+        steps
+            .iter()
+            .filter(|(_, n)| **n + 1 == half)
+            .filter_map(|(c, _)| {
+                let dirs: Vec<Direction> = self.tiles[c]
+                    .directions
+                    .iter()
+                    .filter(|d| steps.get(&(*c + d.to_coord())) == Some(&half))
+                    .copied()
+                    .collect();
+                (!dirs.is_empty()).then_some((*c, dirs))
+            })
+            .collect()
+    }
 
-                // TODO: handle forced
-                // if self.tiles[&c].forced && !self.tiles[&c].directions.contains(&new_d) {
-                //     continue;
-                // }
+    /// Start fields from the front of the grid to the back, facing the
+    /// direction with the fewest steps to the start line
+    pub fn detect_grid(&self) -> Vec<(Coord, Direction)> {
+        let distances = self.distance_map(&self.start_line);
+        let mut grid: Vec<(usize, Coord, Direction)> = self
+            .tiles
+            .iter()
+            .filter(|(_, t)| t.start_field)
+            .filter_map(|(c, t)| {
+                t.directions
+                    .iter()
+                    .filter_map(|d| distances.get(&(*c, *d)).map(|bredth| (*bredth, *c, *d)))
+                    .min_by_key(|(bredth, _, _)| *bredth)
+            })
+            .collect();
+        grid.sort_by_key(|(bredth, c, _)| (*bredth, *c));
+        grid.into_iter().map(|(_, c, d)| (c, d)).collect()
+    }
 
-                stk.push(((new_c, new_d), bredth+1));
-            }
+    /// Fill in the start line, mid line and grid the board lacks with the detected ones
+    pub fn detect_lines(&mut self) {
+        if self.start_line.is_empty() {
+            self.start_line = self.detect_start_line();
+        }
+        if self.mid_line.is_empty() {
+            self.mid_line = self.detect_mid_line();
+        }
+        if self.player_builder.placements.is_empty() {
+            let grid = self.detect_grid();
+            self.player_builder.total = grid.len().min(self.player_builder.colors.len());
+            self.player_builder.placements = grid;
         }
+    }
 
-        for (c, _t) in &self.tiles {
-            let mut dirs = vec![];
-            for ((sc, sd), bredth) in &visit_order {
-                if *c == *sc && *bredth != -1 {
-                    dirs.push((*bredth, *sd));
-                }
+    /// Check if the last step of the player crossed the mid line or the
+    /// goal line, `old_dir` is the direction before the step
+    pub fn update_lap(&self, player: &mut Player, old_dir: Direction) {
+        let old_pos = player.old_position;
+        let crossed = |line: &Vec<(Coord, Vec<Direction>)>| {
+            line.iter().any(|(c, dirs)| {
+                old_pos == *c && player.position != old_pos && dirs.contains(&old_dir)
+            })
+        };
+
+        if player.first_half {
+            if crossed(&self.mid_line) {
+                player.first_half = false;
             }
-            dirs.shuffle(&mut rand::rng());
-            dirs.sort_by(|x,y| x.0.cmp(&y.0));
-            shortest_dist_map.insert(*c, dirs.iter().map(|x| x.1).collect());
+        } else if crossed(&self.start_line) {
+            player.round += 1;
+            player.first_half = true;
         }
-
-        shortest_dist_map
     }
 
-    pub fn draw(&self, canvas: &mut Canvas<Window>, start: Coord, scale: f64) {
+    pub fn draw(&self, canvas: &mut impl Painter, start: Coord, scale: f64) {
         // Draw
         canvas.set_draw_color(Color::RGB(0, 0, 0));
 
@@ -245,18 +328,24 @@ impl HexMap {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum PlayerStepStrategy {
     Best,
     Manual,
+    External, // Turns are supplied through `GameState::external_turn` (see `env.rs`)
+    Optimal,  // Single player optimal policy from `Solver`
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum PlayerGearStrategy {
     Best,
     Manual,
+    External, // Gear changes are supplied through `GameState::external_gear`
+    Optimal,
 }
 
+const OVERLAY_GAMES: usize = 50;
+
 pub struct GameState<'a> {
     pub map: HexMap,
     pub players: Vec<Player>,
@@ -265,20 +354,61 @@ pub struct GameState<'a> {
     pub player_index: usize,
     pub rolling: bool,
     pub blockages: Vec<Coord>,
-    pub shortest_dist_map_mid: BTreeMap<Coord, Vec<Direction>>,
-    pub shortest_dist_map_goal: BTreeMap<Coord, Vec<Direction>>,
+    pub graph: TrackGraph,
+    pub paths: Paths,
 
     pub start: Coord,
     pub scale: f64,
-    pub canvas: &'a mut Canvas<Window>,
+    pub canvas: Option<&'a mut Canvas<Window>>, // None when running headless
 
-    pub choice_tile_selections: BTreeMap<Coord, Vec<Direction>>,
+    pub choice_locks: ChoiceLocks,
     pub simulate: Option<u64>,
+    pub step_delay: Duration, // Pause after each step of a computer player in the window
+    pub names: Vec<String>,   // Names of the players in turn order
+
+    pub rng: StdRng,
+
+    // Decisions for players using the external strategies
+    pub external_gear: Option<ChangeGear>,
+    pub external_turn: Option<Turn>,
+    pub pending_turns: Option<Vec<Turn>>, // Turns offered to an external player still waiting for a decision
+
+    pub solver: Option<Arc<Solver>>, // Solved on first use by an optimal strategy
+
+    pub overlay: Overlay,
+    pub overlay_stats: Option<SimulationStats>, // Simulated when an overlay first needs it
+    pub overlay_values: BTreeMap<Coord, f64>,
+
+    pub trails: Vec<Vec<Coord>>, // Positions visited by each player
+
+    pub session: Session,
+    pub progress: TrackProgress,
+    pub events: Vec<GameEvent>, // What happened in the last call to `step_game`
+    pub turn_sips: Sips,        // Sips of the current player when the turn started
+}
+
+/// Things happening to a player besides moving
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GameEvent {
+    OutOfFuel { player: usize, lead: usize }, // Lead in fields over the second car
+    Crash { player: usize, crash: Crash },
+    Recovered { player: usize, facing: Direction }, // Reversed back onto the board
+    Chikane(ChikaneEvent),
+    FortyTwo { player: usize, opponent: usize, sips: u32 }, // Named opponent drinking the same
+}
+
+/// Decision an external player is currently waiting on
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Decision {
+    Gear,
+    Turn(Vec<Turn>),
+    Facing(Vec<Direction>), // Back onto the board or before rolling, chosen with a turn (see `Player::facing_after`)
 }
 
 pub struct BestStepStrategy<'a> {
     pub blockages: &'a Vec<Coord>,
-    pub shortest_dist_map: &'a BTreeMap<Coord, Vec<Direction>>,
+    pub field: &'a DistanceField,
+    pub rng: &'a mut StdRng,
 }
 
 impl<'a> StepStrategy for BestStepStrategy<'a> {
@@ -286,12 +416,9 @@ impl<'a> StepStrategy for BestStepStrategy<'a> {
         let dir: Direction = player.direction;
         let pos: Coord = player.position;
 
-        let best_dirs: Vec<Turn> = self.shortest_dist_map[&pos]
-            .clone()
-            .into_iter()
-            .filter_map(|d| dir.turn_to_dir(d))
-            .collect();
-        println!("best dirs {:?}", best_dirs);
+        let mut best_dirs: Vec<Turn> = self.field.best_turns(pos, dir);
+        best_dirs.shuffle(self.rng);
+        trace!("best dirs {:?}", best_dirs);
         Some(if best_dirs.is_empty() {
             if turns.is_empty() {
                 Turn::Straight
@@ -312,13 +439,22 @@ impl<'a> StepStrategy for BestStepStrategy<'a> {
                 if turns.is_empty() {
                     Turn::Straight // Falls off choice (invalid choice dir)
                 } else {
-                    *turns.choose(&mut rand::rng()).unwrap()
+                    *turns.choose(self.rng).unwrap()
                 }
             } else {
                 best_dirs[0]
             }
         })
     }
+
+    // Facing closest to the next line, random between equally close
+    fn facing_strategy(&mut self, player: &Player, facings: &[Direction]) -> Option<Direction> {
+        let from = if player.outside_board() { player.old_position } else { player.position };
+        let steps = |d: &Direction| self.field.get((from, *d)).unwrap_or(usize::MAX);
+        let best = facings.iter().map(steps).min()?;
+        let best_facings: Vec<Direction> = facings.iter().filter(|d| steps(d) == best).copied().collect();
+        best_facings.choose(self.rng).copied()
+    }
 }
 
 pub struct BestGearStrategy {}
@@ -329,6 +465,30 @@ impl GearStrategy for BestGearStrategy {
     }
 }
 
+pub struct ExternalGearStrategy {
+    pub change: ChangeGear,
+}
+
+impl GearStrategy for ExternalGearStrategy {
+    fn gear_strategy(&mut self, _: &Player) -> ChangeGear {
+        self.change
+    }
+}
+
+pub struct ExternalStepStrategy {
+    pub turn: Option<Turn>,
+}
+
+impl StepStrategy for ExternalStepStrategy {
+    fn step_strategy(&mut self, _: &Player, _: &Vec<Turn>, _: &Tile) -> Option<Turn> {
+        self.turn.take()
+    }
+
+    fn facing_strategy(&mut self, player: &Player, facings: &[Direction]) -> Option<Direction> {
+        self.turn.take().map(|t| player.facing_after(t, facings))
+    }
+}
+
 pub struct ManualGearStrategy<'a> {
     event_pump: &'a mut EventPump,
 }
@@ -337,7 +497,7 @@ impl<'a> GearStrategy for ManualGearStrategy<'a> {
     fn gear_strategy(&mut self, player: &Player) -> ChangeGear {
         let gear = player.gear;
         let mut gear_change = ChangeGear::Up;
-        println!("Gear {:?}, Gear change: {:?}", gear, gear_change);
+        eprintln!("Gear {:?}, Gear change: {:?}", gear, gear_change);
 
         'selection: loop {
             for event in self.event_pump.poll_iter() {
@@ -355,7 +515,7 @@ impl<'a> GearStrategy for ManualGearStrategy<'a> {
                             ChangeGear::Up => ChangeGear::Stay,
                             _ => ChangeGear::Down,
                         };
-                        println!("Gear {:?}, Gear change: {:?}", gear, gear_change);
+                        eprintln!("Gear {:?}, Gear change: {:?}", gear, gear_change);
                     }
                     Event::KeyDown {
                         keycode: Some(Keycode::W),
@@ -365,13 +525,13 @@ impl<'a> GearStrategy for ManualGearStrategy<'a> {
                             ChangeGear::Down => ChangeGear::Stay,
                             _ => ChangeGear::Up,
                         };
-                        println!("Gear {:?}, Gear change: {:?}", gear, gear_change);
+                        eprintln!("Gear {:?}, Gear change: {:?}", gear, gear_change);
                     }
                     Event::KeyDown {
                         keycode: Some(Keycode::SPACE),
                         ..
                     } => {
-                        println!("Enter pressed?");
+                        trace!("Gear change {:?} confirmed", gear_change);
                         break 'selection;
                     }
                     _ => {}
@@ -386,16 +546,13 @@ impl<'a> GearStrategy for ManualGearStrategy<'a> {
 
 pub struct ManualStepStrategy<'a> {
     turn: Turn,
+    stop: bool,
     event_pump: &'a mut EventPump,
 }
 
-impl<'a> StepStrategy for ManualStepStrategy<'a> {
-    fn step_strategy(
-        &mut self,
-        _player: &Player,
-        _turns: &Vec<Turn>,
-        _tile: &Tile,
-    ) -> Option<Turn> {
+impl<'a> ManualStepStrategy<'a> {
+    // Turn selected with A and D, once confirmed with space, or stop with X
+    fn poll_turn(&mut self) -> Option<Turn> {
         // println!("Turn {:?}", self.turn);
 
         for event in self.event_pump.poll_iter() {
@@ -432,6 +589,13 @@ impl<'a> StepStrategy for ManualStepStrategy<'a> {
                     // println!("Enter pressed?");
                     return Some(self.turn);
                 }
+                Event::KeyDown {
+                    keycode: Some(Keycode::X),
+                    ..
+                } => {
+                    self.stop = true;
+                    return Some(self.turn);
+                }
                 _ => {}
             }
         }
@@ -440,6 +604,61 @@ impl<'a> StepStrategy for ManualStepStrategy<'a> {
     }
 }
 
+impl<'a> StepStrategy for ManualStepStrategy<'a> {
+    fn step_strategy(
+        &mut self,
+        _player: &Player,
+        _turns: &Vec<Turn>,
+        _tile: &Tile,
+    ) -> Option<Turn> {
+        self.poll_turn()
+    }
+
+    fn stop_strategy(&mut self, _player: &Player) -> bool {
+        self.stop
+    }
+
+    fn facing_strategy(&mut self, player: &Player, facings: &[Direction]) -> Option<Direction> {
+        self.poll_turn().map(|t| player.facing_after(t, facings))
+    }
+
+    // Opponent selected with A and D, confirmed with space
+    fn name_strategy(&mut self, _player: &Player, opponents: &[usize], names: &[String]) -> usize {
+        let mut named = 0;
+        eprintln!("42! Name an opponent: {}", names[opponents[named]]);
+
+        loop {
+            for event in self.event_pump.poll_iter() {
+                match event {
+                    Event::Quit { .. }
+                    | Event::KeyDown {
+                        keycode: Some(Keycode::Escape),
+                        ..
+                    } => panic!(),
+                    Event::KeyDown {
+                        keycode: Some(key @ (Keycode::A | Keycode::D)),
+                        ..
+                    } => {
+                        named = if key == Keycode::D {
+                            (named + 1) % opponents.len()
+                        } else {
+                            (named + opponents.len() - 1) % opponents.len()
+                        };
+                        eprintln!("42! Name an opponent: {}", names[opponents[named]]);
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::SPACE),
+                        ..
+                    } => return opponents[named],
+                    _ => {}
+                }
+            }
+
+            ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+        }
+    }
+}
+
 impl<'a> GameState<'a> {
     pub fn new(
         map: HexMap,
@@ -447,13 +666,6 @@ impl<'a> GameState<'a> {
         scale: f64,
         start: Coord,
     ) {
-        let players = map.player_builder.clone().all_players();
-        let player_index = 0;
-        let rolling = true;
-        let blockages = vec![];
-        let shortest_dist_map_mid = BTreeMap::new();
-        let shortest_dist_map_goal = BTreeMap::new();
-
         let sdl_context = sdl2::init().unwrap();
         let video_subsystem = sdl_context.video().unwrap();
 
@@ -482,35 +694,265 @@ impl<'a> GameState<'a> {
 
         let mut event_pump = sdl_context.event_pump().unwrap();
 
+        // Seats are taken in the window before the race
+        let lobby = Lobby::new(&map, &player_strategies);
+        let Some(lobby) = lobby.run(&mut canvas, &mut event_pump, &video_subsystem.text_input()) else {
+            return;
+        };
+        let mut map = map;
+        let (player_strategies, names) = lobby.apply(&mut map);
+
+        let mut game_state = GameState::headless(map, player_strategies, rand::rng().random());
+        game_state.start = start;
+        game_state.scale = scale;
+        game_state.canvas = Some(&mut canvas);
+        game_state.names = names;
+        match lobby.step_delay() {
+            Some(delay) => game_state.step_delay = delay,
+            None => game_state.simulate = Some(2000),
+        }
+
+        game_state.display(&mut event_pump);
+    }
+
+    pub fn headless(
+        map: HexMap,
+        player_strategies: Vec<(PlayerGearStrategy, PlayerStepStrategy)>,
+        seed: u64,
+    ) -> Self {
+        let players = map.player_builder.clone().all_players();
+        let trails = players.iter().map(|p| vec![p.position]).collect();
+        let names = (0..players.len())
+            .map(|i| format!("Player {}", map.player_builder.entrant(i) + 1))
+            .collect();
+
         let mut game_state = GameState {
+            graph: map.graph(),
             map,
             players,
-            player_index,
-            rolling,
-            blockages,
-            shortest_dist_map_mid,
-            shortest_dist_map_goal,
-            start,
-            scale,
-            canvas: &mut canvas,
-            choice_tile_selections: BTreeMap::new(),
+            player_index: 0,
+            rolling: true,
+            blockages: vec![],
+            paths: Paths::default(),
+            start: Coord::new(0, 0),
+            scale: 1.0,
+            canvas: None,
+            choice_locks: ChoiceLocks::default(),
             player_strategies,
-            simulate: Some(2000), // None,
+            simulate: None,
+            step_delay: Duration::new(0, 1_000_000_000u32 / 60),
+            names,
+            rng: StdRng::seed_from_u64(seed),
+            external_gear: None,
+            external_turn: None,
+            pending_turns: None,
+            solver: None,
+            overlay: Overlay::Off,
+            overlay_stats: None,
+            overlay_values: BTreeMap::new(),
+            trails,
+            session: Session::default(),
+            progress: TrackProgress::default(),
+            events: vec![],
+            turn_sips: Sips::default(),
         };
+        game_state.progress = TrackProgress::new(
+            &game_state.graph,
+            &game_state.map.tiles,
+            &game_state.map.mid_line,
+            &game_state.map.start_line,
+        );
+        game_state.update_paths();
+        game_state
+    }
 
-        game_state.display(&mut event_pump);
+    pub fn solver(&mut self) -> &Solver {
+        if self.solver.is_none() {
+            self.solver = Some(Arc::new(Solver::solve(self.map.clone())));
+        }
+        self.solver.as_ref().unwrap()
+    }
+
+    /// Race until `clock` runs out instead of over a lap count, from the first turn
+    pub fn start_session(&mut self, clock: SessionClock) {
+        self.session = Session::new(Some(clock));
+        self.session.start_turn(self.player_index);
+    }
+
+    /// Laps completed by player `index`, whether past the mid line, and steps left to the next line
+    pub fn standing(&self, index: usize) -> (usize, bool, usize) {
+        let player = &self.players[index];
+        let position = if player.outside_board() { player.old_position } else { player.position };
+        let left = self
+            .paths
+            .field(player.first_half)
+            .distance(position, player.direction)
+            .unwrap_or(usize::MAX);
+        (player.round - 1, !player.first_half, left)
+    }
+
+    /// Fields player `index` is ahead of the second car, none unless leading
+    pub fn lead(&self, index: usize) -> Option<usize> {
+        let driven = |i: usize| {
+            let p = &self.players[i];
+            let position = if p.outside_board() { p.old_position } else { p.position };
+            self.progress.fields(p.round, p.first_half, position, p.direction)
+        };
+        let own = driven(index)?;
+        let second = (0..self.players.len()).filter(|i| *i != index).filter_map(driven).max()?;
+        own.checked_sub(second)
+    }
+
+    // Special rule: a car far enough ahead at the start of its turn has run out of fuel.
+    // It finishes its beer to fill up, drinks a sip for the fault, loses the turn and
+    // starts the next one in first gear.
+    fn out_of_fuel(&mut self) -> bool {
+        let Some(limit) = self.map.player_builder.rules.out_of_fuel else {
+            return false;
+        };
+        let Some(lead) = self.lead(self.player_index).filter(|lead| *lead >= limit) else {
+            return false;
+        };
+
+        let player = &mut self.players[self.player_index];
+        player.sips.beers += 1;
+        player.sips.faults += 1;
+        player.gear = 1;
+        player.stalled = true;
+        trace!("Player {} ran out of fuel {} fields ahead", self.player_index, lead);
+        self.events.push(GameEvent::OutOfFuel { player: self.player_index, lead });
+        true
+    }
+
+    /// Players by laps completed, then by track position
+    pub fn classification(&self) -> Vec<usize> {
+        let mut players: Vec<usize> = (0..self.players.len()).collect();
+        players.sort_by_key(|i| {
+            let (laps, second_half, left) = self.standing(*i);
+            (std::cmp::Reverse((laps, second_half)), left)
+        });
+        players
+    }
+
+    /// Opponent named by player `player` after landing on a 42 field, asked of
+    /// its step strategy, none without opponents
+    pub fn nominate(&mut self, player: usize, event_pump: Option<&mut EventPump>) -> Option<usize> {
+        let opponents: Vec<usize> = self.classification().into_iter().filter(|i| *i != player).collect();
+        if opponents.is_empty() {
+            return None;
+        }
+
+        let (_, step_strat) = self.player_strategies[player];
+        let lander = &self.players[player];
+        Some(match step_strat {
+            PlayerStepStrategy::Best => {
+                let mut strategy = BestStepStrategy {
+                    blockages: &self.blockages,
+                    field: self.paths.field(lander.first_half),
+                    rng: &mut self.rng,
+                };
+                strategy.name_strategy(lander, &opponents, &self.names)
+            }
+            PlayerStepStrategy::Optimal => {
+                let solver = self.solver.get_or_insert_with(|| Arc::new(Solver::solve(self.map.clone())));
+                SolverStepStrategy { solver }.name_strategy(lander, &opponents, &self.names)
+            }
+            PlayerStepStrategy::External => ExternalStepStrategy { turn: None }.name_strategy(lander, &opponents, &self.names),
+            PlayerStepStrategy::Manual => {
+                let mut strategy = ManualStepStrategy {
+                    turn: Turn::Straight,
+                    stop: false,
+                    event_pump: event_pump.expect("Manual play requires a window"),
+                };
+                strategy.name_strategy(lander, &opponents, &self.names)
+            }
+        })
+    }
+
+    /// Save the board, players and trails as PNG or SVG
+    pub fn export(&self, path: &Path, scale: f64) -> io::Result<()> {
+        export(path, &self.map, &self.players, &self.trails, scale)
+    }
+
+    pub fn toggle_overlay(&mut self) {
+        self.overlay = self.overlay.next();
+        eprintln!("Overlay: {:?}", self.overlay);
+
+        if self.overlay.needs_simulation() && self.overlay_stats.is_none() {
+            eprintln!("Simulating {} games for overlay", OVERLAY_GAMES);
+            let strategies = self.player_strategies[..self.players.len()]
+                .iter()
+                .map(|(g, s)| match (g, s) {
+                    (PlayerGearStrategy::Optimal, PlayerStepStrategy::Optimal) => (*g, *s),
+                    _ => (PlayerGearStrategy::Best, PlayerStepStrategy::Best),
+                })
+                .collect();
+            let mut simulation = Simulation::new(self.map.clone(), strategies);
+            simulation.solver = self.solver.clone();
+            self.overlay_stats = Some(simulation.run(OVERLAY_GAMES, self.rng.random()));
+        }
+
+        self.overlay_values = match &self.overlay_stats {
+            Some(stats) => self.overlay.values(&self.map, stats),
+            None => self.overlay.values(&self.map, &SimulationStats::default()),
+        };
+    }
+
+    fn draw_board(&mut self) {
+        let Some(canvas) = self.canvas.as_deref_mut() else {
+            return;
+        };
+        self.map.draw(canvas, self.start, self.scale);
+        self.choice_locks.draw(canvas, self.players[self.player_index].round, self.start, self.scale);
+        draw_overlay(canvas, &self.overlay_values, self.start, self.scale);
+    }
+
+    pub fn update_paths(&mut self) {
+        self.blockages = self.update_gameboard();
+        let player = &self.players[self.player_index];
+        let obstacles = Obstacles {
+            blocked: self.blockages.iter().copied().collect(),
+            locked: self.choice_locks.round(player.round),
+            pit_closed: player.pit_closed(),
+        };
+        self.paths.update(&self.graph, &self.map.tiles, &self.map.mid_line, &self.map.start_line, player.round, obstacles);
+    }
+
+    pub fn awaiting_external(&self) -> Option<Decision> {
+        let (gear_strat, step_strat) = self.player_strategies[self.player_index];
+        let player = &self.players[self.player_index];
+        if self.rolling && !player.outside_board() && !player.facing_before_roll() && gear_strat == PlayerGearStrategy::External && self.external_gear.is_none() {
+            Some(Decision::Gear)
+        } else if step_strat == PlayerStepStrategy::External && let Some(turns) = &self.pending_turns {
+            Some(match self.facings() {
+                Some(facings) => Decision::Facing(facings),
+                None => Decision::Turn(turns.clone()),
+            })
+        } else {
+            None
+        }
+    }
+
+    // Facings the current player chooses between instead of stepping, when reversing
+    // back onto the board or before rolling (see `Player::facing_before_roll`)
+    fn facings(&self) -> Option<Vec<Direction>> {
+        let player = &self.players[self.player_index];
+        if player.outside_board() {
+            Some(player.reentry_facings(&self.map.tiles, self.choice_locks.get(player.old_position, player.round)))
+        } else if self.rolling && player.facing_before_roll() {
+            Some(player.start_facings(&self.map.tiles, self.choice_locks.get(player.position, player.round)))
+        } else {
+            None
+        }
     }
 
     pub fn update_gameboard(&mut self) -> Vec<Coord> {
         let mut player_positions = vec![];
 
-        let mut blockages = vec![];
+        let mut blockages = chikane::blocked(&self.map.tiles, &self.players);
         for p in &self.players {
-            if player_positions.contains(&p.position)
-                || self.map.tiles.contains_key(&p.position)
-                    && self.map.tiles[&p.position].chikane
-                    && p.turned_over
-            {
+            let stacked = player_positions.iter().filter(|c| **c == p.position).count() + 1;
+            if stacked >= self.map.player_builder.rules.stack_limit && !blockages.contains(&p.position) {
                 blockages.push(p.position);
             }
             player_positions.push(p.position);
@@ -519,8 +961,126 @@ impl<'a> GameState<'a> {
         blockages
     }
 
-    pub fn step_game(&mut self, event_pump: &mut EventPump) -> bool {
-        if self.rolling {
+    // Let the strategy of the current player step with `turns` or pick one of
+    // `facings`, returns `false` while an external player has not decided
+    fn decide(
+        &mut self,
+        turns: Vec<Turn>,
+        facings: &Option<Vec<Direction>>,
+        event_pump: Option<&mut EventPump>,
+    ) -> bool {
+        let (_, step_strat) = self.player_strategies[self.player_index].clone();
+
+        match step_strat {
+            PlayerStepStrategy::Best => {
+                let mut strategy = BestStepStrategy {
+                    blockages: &self.blockages,
+                    field: self.paths.field(self.players[self.player_index].first_half),
+                    rng: &mut self.rng,
+                };
+                self.players[self.player_index].decide(
+                    &turns,
+                    facings,
+                    &self.graph,
+                    &mut strategy,
+                );
+            }
+            PlayerStepStrategy::Optimal => {
+                self.solver();
+                let mut strategy = SolverStepStrategy {
+                    solver: self.solver.as_ref().unwrap(),
+                };
+                self.players[self.player_index].decide(
+                    &turns,
+                    facings,
+                    &self.graph,
+                    &mut strategy,
+                );
+            }
+            PlayerStepStrategy::External => {
+                let mut strategy = ExternalStepStrategy {
+                    turn: self.external_turn.take(),
+                };
+                if !self.players[self.player_index].decide(
+                    &turns,
+                    facings,
+                    &self.graph,
+                    &mut strategy,
+                ) {
+                    self.pending_turns = Some(turns);
+                    return false; // Waiting for decision
+                }
+            }
+            PlayerStepStrategy::Manual => {
+                let mut strategy = ManualStepStrategy {
+                    turn: Turn::Straight,
+                    stop: false,
+                    event_pump: event_pump.expect("Manual play requires a window"),
+                };
+
+                loop {
+                    if self.players[self.player_index].decide(
+                        &turns,
+                        facings,
+                        &self.graph,
+                        &mut strategy,
+                    ) {
+                        break;
+                    }
+
+                    // Render only needed for manual strategy
+                    let player_dir = self.players[self.player_index].direction;
+                    self.players[self.player_index].direction =
+                        self.players[self.player_index].direction + strategy.turn;
+
+                    self.render();
+                    if let Some(canvas) = self.canvas.as_deref_mut() {
+                        canvas.present();
+                    }
+
+                    // Reset value
+                    self.players[self.player_index].direction = player_dir;
+
+                    ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+                }
+            }
+        }
+
+        true
+    }
+
+    pub fn step_game(&mut self, mut event_pump: Option<&mut EventPump>) -> bool {
+        if self.session.over {
+            return false;
+        }
+        self.events.clear();
+
+        if self.rolling && self.players[self.player_index].outside_board() {
+            self.rolling = false; // No dice when reversing back onto the board
+        } else if self.rolling {
+            if self.out_of_fuel() {
+                self.next_player();
+                return true;
+            }
+
+            let player = &mut self.players[self.player_index];
+            chikane::start_turn(player);
+            self.turn_sips = player.sips.clone();
+            if let Some(tile) = self.map.tiles.get(&player.position) {
+                for effect in tile.all_effects() {
+                    effect.start_of_turn(player);
+                }
+            }
+
+            // Racerbile cars are placed on the grid facing a chosen direction,
+            // Babyracerbile cars face freely every turn
+            if self.players[self.player_index].facing_before_roll() {
+                let turns = self.pending_turns.take().unwrap_or_default();
+                if !self.decide(turns, &self.facings(), event_pump.as_deref_mut()) {
+                    return false; // Waiting for decision
+                }
+            }
+
             // p.roll_dice(BestGearStrategy {});
             {
                 let (gear_strat, _) = self.player_strategies[self.player_index].clone();
@@ -528,116 +1088,90 @@ impl<'a> GameState<'a> {
                 match gear_strat {
                     PlayerGearStrategy::Best => {
                         let strategy = BestGearStrategy {};
-                        self.players[self.player_index].roll_dice(strategy);
+                        self.players[self.player_index].roll_dice(strategy, &mut self.rng);
                     }
                     PlayerGearStrategy::Manual => {
                         let strategy = ManualGearStrategy {
-                            event_pump: event_pump,
+                            event_pump: event_pump.expect("Manual play requires a window"),
                         };
-                        self.players[self.player_index].roll_dice(strategy);
+                        self.players[self.player_index].roll_dice(strategy, &mut self.rng);
+                    }
+                    PlayerGearStrategy::Optimal => {
+                        self.solver();
+                        let strategy = SolverGearStrategy {
+                            solver: self.solver.as_ref().unwrap(),
+                        };
+                        self.players[self.player_index].roll_dice(strategy, &mut self.rng);
+                    }
+                    PlayerGearStrategy::External => {
+                        let Some(change) = self.external_gear.take() else {
+                            return false; // Waiting for decision
+                        };
+                        let strategy = ExternalGearStrategy { change };
+                        self.players[self.player_index].roll_dice(strategy, &mut self.rng);
                     }
                 };
             }
             self.rolling = false;
+            self.update_paths(); // The gear decides if the pit lane is open
         } else {
             let old_dir = self.players[self.player_index].direction;
+            let reentering = self.players[self.player_index].outside_board();
+            let oriented = self.players[self.player_index].oriented;
+            let reversing = self.players[self.player_index].reversing();
+
+            // The whole turn after leaving the board is spent reversing back onto it
+            let facings = self.facings();
+
+            let turns = match self.pending_turns.take() {
+                Some(turns) => Some(turns),
+                None if facings.is_some() => Some(vec![]),
+                None => self.players[self.player_index].pre_step(
+                    &self.graph,
+                    &self.blockages,
+                    &self.choice_locks,
+                ),
+            };
+
+            if let Some(turns) = turns && !self.decide(turns, &facings, event_pump.as_deref_mut()) {
+                return false; // Waiting for decision
+            }
 
-            if let Some(turns) = self.players[self.player_index].pre_step(
-                &self.map.tiles,
-                &self.blockages,
-                &self.choice_tile_selections,
-            ) {
-                let (_, step_strat) = self.player_strategies[self.player_index].clone();
-
-                match step_strat {
-                    PlayerStepStrategy::Best => {
-                        let mut strategy = BestStepStrategy {
-                            blockages: &self.blockages,
-                            shortest_dist_map: (if self.players[self.player_index].first_half {&self.shortest_dist_map_mid } else {&self.shortest_dist_map_goal}),
-                        };
-                        self.players[self.player_index].step(
-                            &turns,
-                            &self.map.tiles,
-                            &mut strategy,
-                        );
-                    }
-                    PlayerStepStrategy::Manual => {
-                        let mut strategy = ManualStepStrategy {
-                            turn: Turn::Straight,
-                            event_pump: event_pump,
-                        };
-
-                        loop {
-                            if self.players[self.player_index].step(
-                                &turns,
-                                &self.map.tiles,
-                                &mut strategy,
-                            ) {
-                                break;
-                            }
-
-                            // Render only needed for manual strategy
-                            let player_dir = self.players[self.player_index].direction;
-                            self.players[self.player_index].direction =
-                                self.players[self.player_index].direction + strategy.turn;
-
-                            self.render();
-                            self.canvas.present();
-
-                            // Reset value
-                            self.players[self.player_index].direction = player_dir;
+            if reentering {
+                let facing = self.players[self.player_index].direction;
+                self.events.push(GameEvent::Recovered { player: self.player_index, facing });
+                self.trails[self.player_index].push(self.players[self.player_index].position);
+                self.next_player();
+                return true;
+            }
+            if let Some(crash) = &self.players[self.player_index].crash {
+                trace!("Off the board from {:?} with {} fields unmoved", crash.position, crash.unmoved);
+                self.events.push(GameEvent::Crash { player: self.player_index, crash: crash.clone() });
+            }
 
-                            ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
-                        }
-                    }
-                }
+            if !oriented {
+                return false; // Turned on the first tile without moving
             }
 
-            // Check if old tile is choice tile, and set direction
+            // The first car through a choice tile in a lap picks the branch for that lap,
+            // driving back to a fork neither picks a branch nor crosses a line
             let old_pos = self.players[self.player_index].old_position;
-            if self.map.tiles.contains_key(&old_pos) && self.map.tiles[&old_pos].choice {
-                if !self.choice_tile_selections.contains_key(&old_pos) {
-                    self.choice_tile_selections.insert(old_pos, vec![]);
-                }
-
-                if self.choice_tile_selections[&old_pos].len()
-                    < self.players[self.player_index].round
-                {
-                    let step_direction = old_dir;
-                    if self.map.tiles[&old_pos]
-                        .directions
-                        .contains(&step_direction)
-                    {
-                        self.choice_tile_selections
-                            .get_mut(&old_pos)
-                            .unwrap()
-                            .push(step_direction);
-                    }
-                }
+            let round = self.players[self.player_index].round;
+            if !reversing
+                && self.map.tiles.get(&old_pos).is_some_and(|t| t.choice && t.directions.contains(&old_dir))
+                && self.choice_locks.lock(old_pos, round, old_dir)
+            {
+                trace!("Branch {:?} picked at {:?} for lap {}", old_dir, old_pos, round);
             }
 
             // Passed midline / goal line
-            if self.players[self.player_index].first_half {
-                for (c, dirs) in &self.map.mid_line {
-                    if old_pos == *c
-                        && self.players[self.player_index].position != old_pos
-                        && dirs.contains(&old_dir)
-                    {
-                        // Cross line
-                        self.players[self.player_index].first_half = false;
-                    }
-                }
-            } else {
-                for (c, dirs) in &self.map.start_line {
-                    if old_pos == *c
-                        && self.players[self.player_index].position != old_pos
-                        && dirs.contains(&old_dir)
-                    {
-                        // Cross line
-                        self.players[self.player_index].round += 1;
-                        self.players[self.player_index].first_half = true;
-                    }
-                }
+            if !reversing {
+                self.map.update_lap(&mut self.players[self.player_index], old_dir);
+            }
+
+            let position = self.players[self.player_index].position;
+            if self.trails[self.player_index].last() != Some(&position) {
+                self.trails[self.player_index].push(position);
             }
 
             // Go to next player
@@ -649,66 +1183,16 @@ impl<'a> GameState<'a> {
                 {
                     let player_pos = self.players[self.player_index].position;
 
-                    if self.map.tiles[&player_pos].blue {
-                        self.players[self.player_index].forced_gear_down = true;
-                    }
-
-                    if self.map.tiles[&player_pos].rotate {
-                        self.players[self.player_index].direction = *vec![
-                            Direction::U,
-                            Direction::UR,
-                            Direction::DR,
-                            Direction::UL,
-                            Direction::DL,
-                            Direction::D,
-                        ]
-                        .choose(&mut rand::rng())
-                        .unwrap();
-                    }
+                    // Before any rotation, bonks go in the direction the car drove
+                    let events = chikane::end_turn(&self.map.tiles, &mut self.players, self.player_index);
+                    self.events.extend(events.into_iter().map(GameEvent::Chikane));
 
-                    if self.map.tiles[&player_pos].chikane {
-                        println!(
-                            "Number of players at {:?} is {:?}",
-                            player_pos,
-                            self.players
-                                .iter()
-                                .filter(|x| x.position == player_pos)
-                                .count()
-                        );
-                        if self
-                            .players
-                            .iter()
-                            .filter(|x| x.position == player_pos)
-                            .count()
-                            == 1
-                        {
-                            self.players[self.player_index].turned_over = true;
-                        }
-                    }
-
-                    let player_dir = self.players[self.player_index].direction;
-                    let bonked_pos = player_pos + player_dir.to_coord();
-
-                    if self.players[self.player_index].bonked
-                        && self.map.tiles.contains_key(&bonked_pos)
-                        && self.map.tiles[&bonked_pos].chikane
-                    {
-                        // Flip player over after bonking
-                        for p in self.players.iter_mut().filter(|x| x.position == bonked_pos) {
-                            // TODO: Should be able to use `.find().unwrap()` instead
-                            p.turned_over = false;
-                        }
+                    for effect in self.map.tiles[&player_pos].all_effects() {
+                        effect.on_land(self, self.player_index, event_pump.as_deref_mut());
                     }
                 }
 
-                self.rolling = true;
-                self.player_index = (self.player_index + 1) % self.players.len();
-                self.blockages = self.update_gameboard();
-                self.shortest_dist_map_goal = self.map.shortest_path(&self.map.start_line.clone());
-                self.shortest_dist_map_mid = self.map.shortest_path(&self.map.mid_line.clone());
-
-                println!("\nPlayer {}'s turn", self.player_index);
-
+                self.next_player();
                 return true;
             }
         }
@@ -716,26 +1200,49 @@ impl<'a> GameState<'a> {
         return false;
     }
 
+    fn next_player(&mut self) {
+        self.players[self.player_index].oriented = false;
+        self.session.end_turn(self.player_index, self.players.len());
+        self.rolling = true;
+        self.player_index = (self.player_index + 1) % self.players.len();
+        self.session.start_turn(self.player_index);
+        self.update_paths();
+
+        trace!("\nPlayer {}'s turn", self.player_index);
+    }
+
     pub fn render(&mut self) {
         if self.simulate.is_none() {
-            self.map.draw(self.canvas, self.start, self.scale);
+            self.draw_board();
         }
 
+        let Some(canvas) = self.canvas.as_deref_mut() else {
+            return; // Headless
+        };
+
         for p in &self.players {
-            p.draw(self.canvas, self.start, self.scale);
+            p.draw(canvas, self.start, self.scale);
+        }
+
+        if self.simulate.is_none() {
+            let player = &self.players[self.player_index];
+            canvas.set_draw_color(player.color);
+            draw_text(canvas, &caption(&self.names[self.player_index], player), 8, 8, CAPTION_SIZE);
         }
     }
 
     pub fn display(&mut self, event_pump: &mut EventPump) {
-        self.blockages = self.update_gameboard();
-        self.shortest_dist_map_goal = self.map.shortest_path(&self.map.start_line.clone());
-        self.shortest_dist_map_mid = self.map.shortest_path(&self.map.mid_line.clone());
+        self.update_paths();
+
+        if self.canvas.is_none() {
+            return; // Nothing to display on
+        }
 
         self.render();
         if self.simulate.is_some() {
-            self.map.draw(self.canvas, self.start, self.scale);
+            self.draw_board();
         }
-        self.canvas.present();
+        self.canvas.as_deref_mut().unwrap().present();
 
         let mut iters = self.simulate.unwrap_or_default();
 
@@ -748,6 +1255,20 @@ impl<'a> GameState<'a> {
                         keycode: Some(Keycode::Escape),
                         ..
                     } => break 'game,
+                    Event::KeyDown {
+                        keycode: Some(Keycode::O),
+                        ..
+                    } => {
+                        self.toggle_overlay();
+                        iters = self.simulate.unwrap_or_default(); // Redraw board
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::P),
+                        ..
+                    } => match self.export(Path::new("snapshot.png"), self.scale) {
+                        Ok(()) => eprintln!("Saved snapshot.png"),
+                        Err(e) => eprintln!("Could not save snapshot: {}", e),
+                    },
                     // Event::KeyDown {
                     //     keycode: Some(Keycode::Space),
                     //     ..
@@ -758,28 +1279,36 @@ impl<'a> GameState<'a> {
                 }
             }
 
-            self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+            let canvas = self.canvas.as_deref_mut().unwrap();
+            canvas.set_draw_color(Color::RGB(0, 0, 0));
             if let Some(bound) = self.simulate {
                 if iters >= bound {
-                    self.canvas.clear();
-                    self.map.draw(self.canvas, self.start, self.scale);
+                    canvas.clear();
+                    self.draw_board();
                     iters = 0;
                 }
                 iters += 1;
             } else {
-                self.canvas.clear();
+                canvas.clear();
             }
 
+            // Human turns wait for their keys, computer turns are paced
+            let (_, step_strategy) = self.player_strategies[self.player_index];
+            let delay = match step_strategy {
+                PlayerStepStrategy::Manual => Duration::new(0, 1_000_000_000u32 / 60),
+                _ => self.step_delay,
+            };
+
             // if step_game {
-            self.step_game(event_pump);
-            println!("====");
+            self.step_game(Some(event_pump));
+            trace!("====");
             // }
 
             self.render();
 
             if self.simulate.is_none() || iters + 2 >= self.simulate.unwrap() {
-                self.canvas.present();
-                ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+                self.canvas.as_deref_mut().unwrap().present();
+                ::std::thread::sleep(if self.simulate.is_none() { delay } else { Duration::new(0, 1_000_000_000u32 / 60) });
             }
         }
     }
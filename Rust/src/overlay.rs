@@ -0,0 +1,97 @@
+use crate::canvas_draw::*;
+use crate::map::*;
+use crate::simulation::*;
+use crate::util::*;
+use sdl2::pixels::Color;
+use std::collections::BTreeMap;
+
+// Heatmaps drawn on top of the board, toggled with `O` in the window
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Overlay {
+    Off,
+    Visits,
+    Crashes,
+    Bonks,
+    Gear,
+    Distance,
+}
+
+impl Overlay {
+    pub fn next(self) -> Self {
+        match self {
+            Overlay::Off => Overlay::Visits,
+            Overlay::Visits => Overlay::Crashes,
+            Overlay::Crashes => Overlay::Bonks,
+            Overlay::Bonks => Overlay::Gear,
+            Overlay::Gear => Overlay::Distance,
+            Overlay::Distance => Overlay::Off,
+        }
+    }
+
+    pub fn needs_simulation(self) -> bool {
+        !matches!(self, Overlay::Off | Overlay::Distance)
+    }
+
+    /// Value in [0, 1] for each hex with data
+    pub fn values(self, map: &HexMap, stats: &SimulationStats) -> BTreeMap<Coord, f64> {
+        let values: BTreeMap<Coord, f64> = match self {
+            Overlay::Off => BTreeMap::new(),
+            Overlay::Visits => stats.hexes.iter().map(|(c, h)| (*c, h.visits as f64)).collect(),
+            Overlay::Crashes => stats.hexes.iter().map(|(c, h)| (*c, h.crashes as f64)).collect(),
+            Overlay::Bonks => stats.hexes.iter().map(|(c, h)| (*c, h.bonks as f64)).collect(),
+            Overlay::Gear => {
+                return stats
+                    .hexes
+                    .iter()
+                    .filter(|(_, h)| h.visits > 0)
+                    .map(|(c, h)| (*c, (h.gear_total as f64 / h.visits as f64 - 1.0) / 2.0))
+                    .collect();
+            }
+            Overlay::Distance => {
+                let distances = map.distance_map(&map.start_line);
+                map.tiles
+                    .keys()
+                    .filter_map(|c| {
+                        (0..6)
+                            .filter_map(|d| distances.get(&(*c, Direction::from_num(d))))
+                            .min()
+                            .map(|b| (*c, *b as f64))
+                    })
+                    .collect()
+            }
+        };
+
+        let max = values.values().cloned().fold(0.0, f64::max);
+        if max <= 0.0 {
+            return BTreeMap::new();
+        }
+        values
+            .into_iter()
+            .filter(|(c, _)| map.tiles.contains_key(c))
+            .map(|(c, v)| (c, v / max))
+            .collect()
+    }
+}
+
+// Blue (low) over green to red (high)
+pub fn heat_color(v: f64) -> Color {
+    let v = v.clamp(0.0, 1.0);
+    let r = (255.0 * (2.0 * v - 1.0).max(0.0)) as u8;
+    let g = (255.0 * (1.0 - (2.0 * v - 1.0).abs())) as u8;
+    let b = (255.0 * (1.0 - 2.0 * v).max(0.0)) as u8;
+    Color::RGB(r, g, b)
+}
+
+pub fn draw_overlay(
+    canvas: &mut impl Painter,
+    values: &BTreeMap<Coord, f64>,
+    start: Coord,
+    scale: f64,
+) {
+    for (c, v) in values {
+        let c = start + *c * (scale as i32);
+        canvas.set_draw_color(heat_color(*v));
+        canvas.fill_hexagon(c.x(), c.y(), scale * 0.6);
+    }
+}
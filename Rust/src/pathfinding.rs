@@ -0,0 +1,345 @@
+use crate::graph::*;
+use crate::util::*;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap};
+
+// Steps left to the next line for every node of the track graph, kept per
+// board for each lap, as the branches picked at choice tiles hold for one lap.
+// When obstacles on the track change, only the nodes around the changed tiles
+// and those whose shortest way ran over them are searched again.
+
+/// Extra steps counted for driving into a blocked tile, about what waiting
+/// for it to clear costs
+const BLOCKED_STEPS: usize = 6;
+
+/// Tiles that change during a race
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Obstacles {
+    pub blocked: BTreeSet<Coord>, // Turned over players on chikanes and tiles with several players
+    pub locked: BTreeMap<Coord, Direction>, // Choice tiles already decided for this round
+    pub pit_closed: bool, // Le Mans: the car is in a gear too high for the pit lane
+}
+
+impl Obstacles {
+    fn allows(&self, tiles: &BTreeMap<Coord, Tile>, (c, d): Node) -> bool {
+        self.locked.get(&c).is_none_or(|l| *l == d) && !(self.pit_closed && enters_pit(tiles, c, c + d.to_coord()))
+    }
+
+    fn cost(&self, c: Coord) -> usize {
+        if self.blocked.contains(&c) { 1 + BLOCKED_STEPS } else { 1 }
+    }
+
+    // Tiles blocked, locked or leading into the pit lane in one but not the other
+    fn changed(&self, other: &Obstacles, tiles: &BTreeMap<Coord, Tile>) -> BTreeSet<Coord> {
+        let mut changed: BTreeSet<Coord> = self.blocked.symmetric_difference(&other.blocked).copied().collect();
+        changed.extend(
+            self.locked
+                .keys()
+                .chain(other.locked.keys())
+                .filter(|c| self.locked.get(c) != other.locked.get(c)),
+        );
+        if self.pit_closed != other.pit_closed {
+            changed.extend(
+                tiles
+                    .keys()
+                    .filter(|c| (0..6).any(|d| enters_pit(tiles, **c, **c + Direction::from_num(d).to_coord()))),
+            );
+        }
+        changed
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct DistanceField {
+    pub distances: BTreeMap<Node, usize>, // Steps from leaving the tile until past the line
+    pub continuations: BTreeMap<Node, Vec<Node>>, // Every next node on a shortest way
+    targets: BTreeSet<Node>, // Nodes right past the line
+}
+
+impl DistanceField {
+    /// Search backwards from the tiles past `line`
+    pub fn new(
+        graph: &TrackGraph,
+        tiles: &BTreeMap<Coord, Tile>,
+        line: &Vec<(Coord, Vec<Direction>)>,
+        obstacles: &Obstacles,
+    ) -> Self {
+        let mut field = DistanceField::default();
+        for (c, dirs) in line {
+            for d in dirs {
+                let past = *c + d.to_coord();
+                if let Some(tile) = tiles.get(&past) {
+                    field.targets.extend(tile.directions.iter().map(|new_d| (past, *new_d)));
+                }
+            }
+        }
+
+        let mut queue = BinaryHeap::new();
+        for target in field.targets.clone() {
+            if !obstacles.allows(tiles, target) {
+                continue;
+            }
+            for p in graph.predecessors(target) {
+                field.relax(tiles, obstacles, &mut queue, *p, target, obstacles.cost(target.0) - 1);
+            }
+        }
+        field.search(graph, tiles, obstacles, queue);
+        field
+    }
+
+    /// Bring the field searched with `before` up to date with `after`, searching
+    /// again only where the changed tiles can make a difference
+    pub fn update(&mut self, graph: &TrackGraph, tiles: &BTreeMap<Coord, Tile>, before: &Obstacles, after: &Obstacles) {
+        // Nodes on and onto the changed tiles, and every node whose shortest way runs over them
+        let mut stale: BTreeSet<Node> = BTreeSet::new();
+        for c in before.changed(after, tiles) {
+            for node in (0..6).map(|d| (c, Direction::from_num(d))) {
+                stale.insert(node);
+                stale.extend(graph.predecessors(node));
+            }
+        }
+        let mut todo: Vec<Node> = stale.iter().copied().collect();
+        while let Some(node) = todo.pop() {
+            for p in graph.predecessors(node) {
+                if !stale.contains(p) && self.continuations.get(p).is_some_and(|c| c.contains(&node)) {
+                    stale.insert(*p);
+                    todo.push(*p);
+                }
+            }
+        }
+        for node in &stale {
+            self.distances.remove(node);
+            self.continuations.remove(node);
+        }
+
+        // Search again from the nodes that kept their distance
+        let mut queue = BinaryHeap::new();
+        for node in &stale {
+            for next in graph.moves(*node).iter().map(|e| e.to) {
+                if self.targets.contains(&next) && after.allows(tiles, next) {
+                    self.relax(tiles, after, &mut queue, *node, next, after.cost(next.0) - 1);
+                }
+                if let Some(bredth) = self.get(next) {
+                    self.relax(tiles, after, &mut queue, *node, next, bredth + after.cost(next.0));
+                }
+            }
+        }
+        self.search(graph, tiles, after, queue);
+    }
+
+    // `node` reaches the line in `bredth` steps by driving on to `next`
+    fn relax(
+        &mut self,
+        tiles: &BTreeMap<Coord, Tile>,
+        obstacles: &Obstacles,
+        queue: &mut BinaryHeap<Reverse<(usize, Node)>>,
+        node: Node,
+        next: Node,
+        bredth: usize,
+    ) {
+        if !obstacles.allows(tiles, node) {
+            return;
+        }
+        match self.distances.get(&node) {
+            Some(b) if *b < bredth => return,
+            Some(b) if *b == bredth => {}
+            _ => {
+                self.distances.insert(node, bredth);
+                self.continuations.insert(node, vec![]);
+                queue.push(Reverse((bredth, node)));
+            }
+        }
+        let continuations = self.continuations.get_mut(&node).unwrap();
+        if !continuations.contains(&next) {
+            continuations.push(next);
+        }
+    }
+
+    fn search(
+        &mut self,
+        graph: &TrackGraph,
+        tiles: &BTreeMap<Coord, Tile>,
+        obstacles: &Obstacles,
+        mut queue: BinaryHeap<Reverse<(usize, Node)>>,
+    ) {
+        while let Some(Reverse((bredth, node))) = queue.pop() {
+            if self.get(node) != Some(bredth) {
+                continue; // Already found shorter
+            }
+            for p in graph.predecessors(node) {
+                self.relax(tiles, obstacles, &mut queue, *p, node, bredth + obstacles.cost(node.0));
+            }
+        }
+    }
+
+    pub fn get(&self, node: Node) -> Option<usize> {
+        self.distances.get(&node).copied()
+    }
+
+    /// Steps needed to get past the line for a player at `position` facing `direction`
+    pub fn distance(&self, position: Coord, direction: Direction) -> Option<usize> {
+        [Turn::Straight, Turn::Left, Turn::Right]
+            .iter()
+            .filter_map(|t| self.get((position, direction + *t)))
+            .min()
+            .map(|bredth| bredth + 1)
+    }
+
+    /// Every turn at `position` on a shortest way past the line
+    pub fn best_turns(&self, position: Coord, direction: Direction) -> Vec<Turn> {
+        let Some(best) = self.distance(position, direction) else {
+            return vec![];
+        };
+        [Turn::Straight, Turn::Left, Turn::Right]
+            .into_iter()
+            .filter(|t| self.get((position, direction + *t)) == Some(best - 1))
+            .collect()
+    }
+}
+
+/// Distance fields to the mid line and the goal
+#[derive(Clone, Default)]
+struct LapPaths {
+    mid: DistanceField,
+    goal: DistanceField,
+    obstacles: Obstacles, // What the fields are searched with
+}
+
+/// Distance fields of every lap a car has been in, for cars with the pit lane
+/// open and closed apart
+#[derive(Clone, Default)]
+pub struct Paths {
+    laps: BTreeMap<(usize, bool), LapPaths>, // By lap and `Obstacles::pit_closed`
+    current: (usize, bool),                  // Fields of the last update
+}
+
+impl Paths {
+    /// Bring the fields of lap `round` up to date with `obstacles`, starting
+    /// from those of another lap the first time. Returns if they changed.
+    pub fn update(
+        &mut self,
+        graph: &TrackGraph,
+        tiles: &BTreeMap<Coord, Tile>,
+        mid_line: &Vec<(Coord, Vec<Direction>)>,
+        start_line: &Vec<(Coord, Vec<Direction>)>,
+        round: usize,
+        obstacles: Obstacles,
+    ) -> bool {
+        self.current = (round, obstacles.pit_closed);
+        if !self.laps.contains_key(&self.current) {
+            let Some((_, paths)) = self.laps.range(..self.current).next_back().or(self.laps.iter().next()) else {
+                let mid = DistanceField::new(graph, tiles, mid_line, &obstacles);
+                let goal = DistanceField::new(graph, tiles, start_line, &obstacles);
+                self.laps.insert(self.current, LapPaths { mid, goal, obstacles });
+                return true;
+            };
+            self.laps.insert(self.current, paths.clone());
+        }
+
+        let paths = self.laps.get_mut(&self.current).unwrap();
+        if paths.obstacles == obstacles {
+            return false;
+        }
+        paths.mid.update(graph, tiles, &paths.obstacles, &obstacles);
+        paths.goal.update(graph, tiles, &paths.obstacles, &obstacles);
+        paths.obstacles = obstacles;
+        true
+    }
+
+    /// Field towards the next line for a player in the first or second half of the lap
+    pub fn field(&self, first_half: bool) -> &DistanceField {
+        let paths = &self.laps[&self.current];
+        if first_half { &paths.mid } else { &paths.goal }
+    }
+}
+
+/// Fields driven along the track since the race started, measured without obstacles
+#[derive(Clone, Default)]
+pub struct TrackProgress {
+    pub mid: DistanceField,
+    pub goal: DistanceField,
+    pub first_leg: usize, // Fields from the start line to the mid line
+    pub lap: usize,
+}
+
+impl TrackProgress {
+    pub fn new(
+        graph: &TrackGraph,
+        tiles: &BTreeMap<Coord, Tile>,
+        mid_line: &Vec<(Coord, Vec<Direction>)>,
+        start_line: &Vec<(Coord, Vec<Direction>)>,
+    ) -> Self {
+        let mid = DistanceField::new(graph, tiles, mid_line, &Obstacles::default());
+        let goal = DistanceField::new(graph, tiles, start_line, &Obstacles::default());
+        // Shortest way from the tiles past one line to the other
+        let leg = |field: &DistanceField, line: &Vec<(Coord, Vec<Direction>)>| {
+            line.iter()
+                .flat_map(|(c, dirs)| dirs.iter().map(|d| (*c + d.to_coord(), *d)))
+                .filter_map(|(c, d)| field.distance(c, d))
+                .min()
+                .unwrap_or(0)
+        };
+        let first_leg = leg(&mid, start_line);
+        let lap = first_leg + leg(&goal, mid_line);
+        TrackProgress { mid, goal, first_leg, lap }
+    }
+
+    /// Fields driven by a car in lap `round` at `position` facing `direction`,
+    /// none where the lines cannot be reached from
+    pub fn fields(&self, round: usize, first_half: bool, position: Coord, direction: Direction) -> Option<usize> {
+        let driven = if first_half {
+            self.first_leg.saturating_sub(self.mid.distance(position, direction)?)
+        } else {
+            self.lap.saturating_sub(self.goal.distance(position, direction)?)
+        };
+        Some((round - 1) * self.lap + driven)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boards::*;
+
+    // Obstacles on every `n`th tile, branches locked to their first arrow
+    fn obstacles(tiles: &BTreeMap<Coord, Tile>, n: usize, pit_closed: bool) -> Obstacles {
+        Obstacles {
+            blocked: tiles.keys().step_by(n).copied().collect(),
+            locked: tiles.iter().filter(|(_, t)| t.choice).step_by(2).map(|(c, t)| (*c, t.directions[0])).collect(),
+            pit_closed,
+        }
+    }
+
+    #[test]
+    fn update_matches_search() {
+        for map in [rtfm(), random_direction()] {
+            let graph = map.graph();
+            let before = obstacles(&map.tiles, 7, false);
+            let after = obstacles(&map.tiles, 5, true);
+            for line in [&map.mid_line, &map.start_line] {
+                let mut field = DistanceField::new(&graph, &map.tiles, line, &before);
+                field.update(&graph, &map.tiles, &before, &after);
+                let fresh = DistanceField::new(&graph, &map.tiles, line, &after);
+                assert_eq!(field.distances, fresh.distances);
+
+                field.update(&graph, &map.tiles, &after, &Obstacles::default());
+                let clear = DistanceField::new(&graph, &map.tiles, line, &Obstacles::default());
+                assert_eq!(field.distances, clear.distances);
+            }
+        }
+    }
+
+    #[test]
+    fn laps_keep_their_fields() {
+        let map = rtfm();
+        let graph = map.graph();
+        let locked = obstacles(&map.tiles, usize::MAX, false);
+        let mut paths = Paths::default();
+        assert!(paths.update(&graph, &map.tiles, &map.mid_line, &map.start_line, 1, Obstacles::default()));
+        assert!(paths.update(&graph, &map.tiles, &map.mid_line, &map.start_line, 2, locked.clone()));
+        let second = paths.field(true).distances.clone();
+        assert!(!paths.update(&graph, &map.tiles, &map.mid_line, &map.start_line, 1, Obstacles::default()));
+        assert!(!paths.update(&graph, &map.tiles, &map.mid_line, &map.start_line, 2, locked));
+        assert_eq!(paths.field(true).distances, second);
+    }
+}
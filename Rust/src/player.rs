@@ -1,11 +1,14 @@
 use crate::canvas_draw::*;
+use crate::graph::*;
+use crate::choice::*;
+use crate::rules::*;
+use crate::trace;
 use crate::util::*;
-use rand::seq::IndexedRandom;
+use rand::Rng;
 use sdl2::pixels::Color;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
 use std::collections::BTreeMap;
 use std::ops::Add;
+use std::sync::Arc;
 use std::cmp::max;
 
 pub struct GeneralError {
@@ -23,29 +26,73 @@ pub struct TechnicalError {
     pub offmap: bool, // half a beer now, half before next turn later
 }
 
+/// Where and how a car left the board
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Crash {
+    pub position: Coord, // Last tile on the board, reversed back onto in the recovery turn
+    pub direction: Direction,
+    pub gear: u8,
+    pub roll: Vec<u8>,
+    pub unmoved: u8, // Fields of the roll not moved on the board, a sip each
+}
+
+/// Sips a player has drunk during the race
+#[derive(Clone, Default, Debug)]
 pub struct Sips {
-    pub start_last: bool,
-    pub end_first: bool,
-    
-    pub remaining_steps: u8,
-    pub fell_out: bool,
-    pub back_on: bool,
-    pub ones: u8,
+    pub start_last: u32,
+    pub end_first: u32,
+
+    pub remaining_steps: u32, // Steps not moved on the board after leaving it
+    pub stopped_early: u32,   // Steps of the roll not moved when stopping before it is used up or blocked
+    pub gear_down: u32, // One for every gear dropped, also when forced
+    pub ones: u32,
+    pub faults: u32, // One for every technical fault, like running out of fuel
+    pub named: u32,  // Drunk for an opponent landing on a 42 field
+
+    pub fell_out: u32,   // Times off the board, no sips in themselves
+    pub beers: u32,      // Beers finished at once, not counted as sips
+    pub whines: u32,     // Direction changes at a hazardous roll, a sip only when forgotten
+    pub two_wheels: u32, // Steps driven on two wheels
 }
 
-// impl Default for Sips {
-//     fn default() -> Sips {
-//         Sips {
-//             start_last: false,
-//             end_first: false,
-//             whines: 0,
-//             remaining_steps: 0,
-//             fell_out: false,
-//             back_on: false,
-//             ones: 0,
-//         }
-//     }
-// }
+impl Sips {
+    pub fn total(&self) -> u32 {
+        self.start_last + self.end_first + self.remaining_steps + self.stopped_early + self.gear_down + self.ones + self.faults + self.named
+    }
+
+    /// Sips drunk after `earlier` was taken
+    pub fn since(&self, earlier: &Sips) -> Sips {
+        Sips {
+            start_last: self.start_last - earlier.start_last,
+            end_first: self.end_first - earlier.end_first,
+            remaining_steps: self.remaining_steps - earlier.remaining_steps,
+            stopped_early: self.stopped_early - earlier.stopped_early,
+            gear_down: self.gear_down - earlier.gear_down,
+            ones: self.ones - earlier.ones,
+            faults: self.faults - earlier.faults,
+            named: self.named - earlier.named,
+            fell_out: self.fell_out - earlier.fell_out,
+            beers: self.beers - earlier.beers,
+            whines: self.whines - earlier.whines,
+            two_wheels: self.two_wheels - earlier.two_wheels,
+        }
+    }
+
+    pub fn add(&mut self, other: &Sips) {
+        self.start_last += other.start_last;
+        self.end_first += other.end_first;
+        self.remaining_steps += other.remaining_steps;
+        self.stopped_early += other.stopped_early;
+        self.gear_down += other.gear_down;
+        self.ones += other.ones;
+        self.faults += other.faults;
+        self.named += other.named;
+        self.fell_out += other.fell_out;
+        self.beers += other.beers;
+        self.whines += other.whines;
+        self.two_wheels += other.two_wheels;
+    }
+}
 
 #[derive(Clone)]
 pub struct Player {
@@ -55,7 +102,8 @@ pub struct Player {
     pub old_position: Coord,
     pub direction: Direction,
 
-    pub outside_board: bool,
+    pub crash: Option<Crash>, // Off the board until the recovery turn reverses back onto it
+    pub wrong_branch: Option<WrongBranch>, // Down the branch not picked for the lap until driven back
     pub next_falls_off: bool,
     pub forced_gear_down: bool,
 
@@ -63,6 +111,7 @@ pub struct Player {
 
     pub roll: Vec<u8>,
     pub steps: u8,
+    pub boost: u8, // Fields added to the move by boost tiles this turn
     pub stalled: bool,
 
     pub bonked: bool,
@@ -71,10 +120,35 @@ pub struct Player {
     pub first_half: bool,
 
     pub turned_over: bool,
+
+    pub sips: Sips,
+
+    pub rules: Arc<RuleSet>,
+    pub oriented: bool, // Facing settled for this turn, before the first move
+    pub on_grid: bool,  // Racerbile facing not chosen yet, done before the first roll
+
+    pub whines: u8,          // Direction changes at a hazardous roll this turn
+    pub on_two_wheels: bool, // Two wheels rule: lifted by the last turn at a hazardous roll
 }
 
 pub trait StepStrategy {
     fn step_strategy(&mut self, player: &Player, turns: &Vec<Turn>, tile: &Tile) -> Option<Turn>;
+
+    /// Stop on the tile instead of driving on, where the rules allow it
+    fn stop_strategy(&mut self, _: &Player) -> bool {
+        false
+    }
+
+    /// Facing when reversing back onto the board, `None` while undecided
+    fn facing_strategy(&mut self, _: &Player, facings: &[Direction]) -> Option<Direction> {
+        facings.first().copied()
+    }
+
+    /// Opponent to drink along after landing on a 42 field, one of `opponents`
+    /// from the best placed down, `names` of all players
+    fn name_strategy(&mut self, _: &Player, opponents: &[usize], _names: &[String]) -> usize {
+        opponents[0]
+    }
 }
 
 pub trait GearStrategy {
@@ -93,7 +167,7 @@ impl Player {
 
     // }
 
-    pub fn draw(&self, canvas: &mut Canvas<Window>, start: Coord, scale: f64) {
+    pub fn draw(&self, canvas: &mut impl Painter, start: Coord, scale: f64) {
         let c = start + self.position * (scale as i32);
 
         if self.turned_over {
@@ -102,6 +176,21 @@ impl Player {
             canvas.set_draw_color(self.color);
         }
 
+        let angle = self.direction.angle();
+        if self.on_two_wheels {
+            // On its edge the cap is seen as a bar along the way it drives
+            let (dx, dy) = (scale * self.radius * angle.cos(), scale * self.radius * angle.sin());
+            let (nx, ny) = (-angle.sin(), angle.cos());
+            for k in -3..=3 {
+                let (ox, oy) = ((k as f64 * nx) as i32, (k as f64 * ny) as i32);
+                let _ = canvas.draw_line(
+                    (c.x() - dx as i32 + ox, c.y() - dy as i32 + oy),
+                    (c.x() + dx as i32 + ox, c.y() + dy as i32 + oy),
+                );
+            }
+            return;
+        }
+
         for i in -1..=1 {
             for j in -1..=1 {
                 let _ = draw_hexagon(canvas, c.x() + i, c.y() + j, scale * self.radius);
@@ -114,7 +203,6 @@ impl Player {
             }
         }
 
-        let angle = self.direction.angle();
         let cx = c.x() + (0.85 * scale / 2_f64.sqrt() * angle.cos()) as i32;
         let cy = c.y() + (0.85 * scale / 2_f64.sqrt() * angle.sin()) as i32;
 
@@ -125,95 +213,278 @@ impl Player {
         }
     }
 
-    pub fn step_possibilities(
-        &self,
-        tiles: &BTreeMap<Coord, Tile>,
-        choice_vec: &Option<&Vec<Direction>>,
-    ) -> Vec<Turn> {
-        if !tiles.contains_key(&self.position) {
+    pub fn step_possibilities(&self, graph: &TrackGraph, lock: Option<Direction>) -> Vec<Turn> {
+        if !graph.tiles().contains_key(&self.position) {
             return vec![Turn::Straight];
         }
+        if lock.is_some() {
+            trace!("Branch {:?} picked for this lap", lock);
+        }
 
-        let curr_tile = tiles[&self.position].clone();
-        let dir = if !curr_tile.forced.is_empty() && curr_tile.forced.contains_key(&self.old_position) {
-            vec![curr_tile.forced[&self.old_position]] // Old position need not be there?
-        } else if let Some(choice_vec) = choice_vec && self.round <= choice_vec.len() {
-            println!("Choice vec: {:?}", choice_vec);
-            println!("Directions {:?} vs default {:?}: ", vec![choice_vec[self.round-1]], curr_tile.directions);
-            vec![choice_vec[self.round-1]] // Only one?
-        } else {
-            curr_tile.directions
-        };
-
-        let result = dir.into_iter()
-            .filter_map(|x| self.direction.turn_to_dir(x))
-            .filter(|x| !(self.roll.iter().fold(0, u8::add) > 9) || *x == Turn::Straight) // If too fast to turn
+        let result = graph
+            .offered(self.old_position, self.direction, lock)
+            .into_iter()
+            .filter(|x| !self.too_fast() || *x == Turn::Straight) // If too fast to turn
             .collect();
 
-        println!("Possible turns: {:?}", result);
+        trace!("Possible turns: {:?}", result);
 
         result
     }
 
+    /// Rolled more than the car can turn with
+    pub fn too_fast(&self) -> bool {
+        self.roll.iter().fold(0, u8::add) > self.rules.turn_limit
+    }
+
+    /// Rolls from `whine_from` up to `turn_limit` make the wheels whine at every direction change
+    pub fn hazardous(&self) -> bool {
+        (self.rules.whine_from..=self.rules.turn_limit).contains(&self.roll.iter().sum())
+    }
+
+    /// The facing is chosen from `start_facings` every turn instead of turning on the first tile
+    pub fn free_facing(&self) -> bool {
+        self.rules.variant == Variant::Babyracerbile
+    }
+
+    /// The facing is still to be chosen before rolling, on the grid or every turn in Babyracerbile
+    pub fn facing_before_roll(&self) -> bool {
+        self.on_grid || self.free_facing() && !self.oriented && self.wrong_branch.is_none()
+    }
+
+    /// Directions the car can face before rolling, the arrows of its tile
+    pub fn start_facings(&self, tiles: &BTreeMap<Coord, Tile>, lock: Option<Direction>) -> Vec<Direction> {
+        let tile = &tiles[&self.position];
+        if let Some(lock) = lock {
+            vec![lock]
+        } else {
+            let facings = marked_directions(tile, self.old_position);
+            if facings.is_empty() { vec![self.direction] } else { facings }
+        }
+    }
+
+    /// Face `facing` before the roll or the first move of the turn
+    pub fn face(&mut self, facing: Direction) {
+        self.direction = facing;
+        self.old_position = self.position;
+        self.oriented = true;
+        self.on_grid = false;
+    }
+
     pub fn pre_step(
         &mut self,
-        tiles: &BTreeMap<Coord, Tile>,
+        graph: &TrackGraph,
         blockages: &Vec<Coord>,
-        choice_vec: &BTreeMap<Coord, Vec<Direction>>,
+        locks: &ChoiceLocks,
     ) -> Option<Vec<Turn>> {
+        if self.wrong_branch.as_ref().is_some_and(|w| w.reversing) && self.drive_back() {
+            return None; // Done
+        }
+
+        if !self.oriented {
+            // Racerbile: turn on the tile the turn starts on, with the speed of the new roll,
+            // as if driving onto it from behind the car
+            self.old_position = self.position - self.direction.to_coord();
+            return Some(self.step_possibilities(graph, locks.get(self.position, self.round)));
+        }
+
         if self.next_falls_off {
             self.old_position = self.position;
             self.position = self.position + self.direction.to_coord();
 
-            self.outside_board = true;
-            self.finished = true;
-            self.stalled = true;
             self.next_falls_off = false;
-
-            return None; // Done
-        } else if self.outside_board {
-            self.outside_board = false;
-            self.position = self.old_position;
-            self.old_position = self.position;
-
-            if let Some(choice_vec) =  choice_vec.get(&self.position) && self.round <= choice_vec.len() {
-                self.direction = choice_vec[self.round-1];
-            } else {
-                self.direction = tiles[&self.position].directions[0]; // TODO: allow stategy when re-entering the board
-            }
+            self.leave_board();
             return None; // Done
         } else {
             self.old_position = self.position;
             self.position = self.position + self.direction.to_coord();
         }
 
-        if blockages.contains(&self.position) {
+        // Stopped in front of a blocked field or a pit lane closed to the car,
+        // a sip for every field not moved
+        let tiles = graph.tiles();
+        let blocked = blockages.contains(&self.position);
+        if blocked || self.pit_closed() && enters_pit(tiles, self.old_position, self.position) {
             self.position = self.old_position;
+            self.sips.stopped_early += self.fields_left() as u32;
             self.finished = true;
-            self.bonked = true;
+            self.bonked = blocked;
             return None; // Done
         }
 
         // Outside bord
         if !tiles.contains_key(&self.position) {
-            self.outside_board = true;
-            self.finished = true;
-            self.stalled = true;
+            self.leave_board();
             return None;
         }
 
-        Some(self.step_possibilities(tiles, &choice_vec.get(&self.position)))
+        // A technical fault for every field driven down the wrong branch
+        if let Some(wrong) = &mut self.wrong_branch {
+            wrong.trail.push(self.position);
+            self.sips.faults += 1;
+        }
+
+        for effect in tiles.get(&self.old_position).map(Tile::all_effects).unwrap_or_default() {
+            effect.on_pass(self);
+        }
+
+        Some(self.step_possibilities(graph, locks.get(self.position, self.round)))
+    }
+
+    /// The next step drives back towards the fork from the wrong branch
+    pub fn reversing(&self) -> bool {
+        self.wrong_branch.as_ref().is_some_and(|w| w.reversing && !w.trail.is_empty())
+    }
+
+    // One field back towards the fork, facing the picked branch once there.
+    // Returns `false` when already at the fork, to go on with the move.
+    fn drive_back(&mut self) -> bool {
+        let Some(wrong) = &mut self.wrong_branch else {
+            return false;
+        };
+        let moved = wrong.trail.pop().is_some();
+        if moved {
+            self.old_position = self.position;
+            self.position = wrong.trail.last().copied().unwrap_or(wrong.fork);
+        }
+        if wrong.trail.is_empty() {
+            trace!("Back at the fork {:?}", wrong.fork);
+            self.direction = wrong.picked;
+            self.wrong_branch = None;
+        }
+        if moved {
+            self.steps += 1;
+            if self.fields_left() == 0 {
+                self.finished = true;
+            }
+        }
+        moved
+    }
+
+    /// Le Mans: the pit lane is closed to a car in a gear above the pit gear
+    pub fn pit_closed(&self) -> bool {
+        self.rules.le_mans.as_ref().is_some_and(|le_mans| self.gear > le_mans.pit_gear)
+    }
+
+    pub fn outside_board(&self) -> bool {
+        self.crash.is_some()
+    }
+
+    /// Fields of the move not driven yet, the roll and any boost picked up on the way
+    pub fn fields_left(&self) -> u8 {
+        (self.roll.iter().sum::<u8>() + self.boost).saturating_sub(self.steps)
+    }
+
+    // One sip for every field of the move not moved on the board
+    fn leave_board(&mut self) {
+        let unmoved = self.fields_left();
+        self.sips.remaining_steps += unmoved as u32;
+        self.sips.fell_out += 1;
+        if self.rules.reentry == Reentry::FinishBeer {
+            self.sips.beers += 1;
+        }
+
+        self.crash = Some(Crash {
+            position: self.old_position,
+            direction: self.direction,
+            gear: self.gear,
+            roll: self.roll.clone(),
+            unmoved,
+        });
+        self.on_two_wheels = false;
+        self.finished = true;
+    }
+
+    /// Directions the player can face when reversing back onto the tile it left the board from
+    pub fn reentry_facings(&self, tiles: &BTreeMap<Coord, Tile>, lock: Option<Direction>) -> Vec<Direction> {
+        if let Some(lock) = lock {
+            vec![lock]
+        } else if tiles[&self.old_position].directions.is_empty() {
+            vec![self.direction]
+        } else {
+            tiles[&self.old_position].directions.clone()
+        }
+    }
+
+    /// Facing after turning from the direction the player left the board in,
+    /// the first of `facings` if that is not one of them
+    pub fn facing_after(&self, turn: Turn, facings: &[Direction]) -> Direction {
+        let facing = self.direction + turn;
+        if facings.contains(&facing) { facing } else { facings[0] }
+    }
+
+    /// Reversing back onto the board takes the whole recovery turn, in first gear
+    pub fn reenter(&mut self, facing: Direction) {
+        self.sips.gear_down += (self.gear - 1) as u32;
+        self.gear = 1;
+        self.forced_gear_down = false;
+
+        let crash = self.crash.take().expect("Only cars off the board reenter");
+        self.position = crash.position;
+        self.direction = facing;
+        self.roll = vec![];
+        self.steps = 0;
+        self.boost = 0;
+        self.finished = true;
+    }
+
+    /// Face one of `facings` when given (back onto the board or before rolling),
+    /// otherwise step with `turns`. Returns `false` while the strategy has not decided.
+    pub fn decide(
+        &mut self,
+        turns: &Vec<Turn>,
+        facings: &Option<Vec<Direction>>,
+        graph: &TrackGraph,
+        strategy: &mut impl StepStrategy,
+    ) -> bool {
+        let Some(facings) = facings else {
+            return self.step(turns, graph, strategy);
+        };
+        match strategy.facing_strategy(self, facings) {
+            Some(facing) if self.outside_board() => {
+                trace!("Back on the board at {:?} facing {:?}", self.old_position, facing);
+                self.reenter(facing);
+                true
+            }
+            Some(facing) => {
+                trace!("Facing {:?}", facing);
+                self.face(facing);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stopping here instead of driving on with `turn` is allowed, never after a roll too fast to turn
+    pub fn may_stop(&self, graph: &TrackGraph, turn: Turn) -> bool {
+        if self.too_fast() {
+            return false;
+        }
+        match self.rules.stop_early {
+            StopEarly::Anywhere => true,
+            StopEarly::Chikane => {
+                let ahead = self.position + (self.direction + turn).to_coord();
+                graph.tiles().get(&ahead).is_some_and(|t| t.chikane)
+            }
+        }
     }
 
     pub fn step(
         &mut self,
         turns: &Vec<Turn>,
-        tiles: &BTreeMap<Coord, Tile>,
+        graph: &TrackGraph,
         strategy: &mut impl StepStrategy,
     ) -> bool {
-        let curr_tile = tiles[&self.position].clone();
+        let curr_tile = graph.tiles()[&self.position].clone();
 
-        let Some(mut turn) = (if self.roll.iter().fold(0, u8::add) > 9 {
+        // The car keeps facing the direction it drove onto the last tile
+        if self.oriented && self.fields_left() == 1 {
+            self.steps += 1;
+            self.finished = true;
+            return true;
+        }
+
+        let Some(mut turn) = (if self.too_fast() {
             Some(Turn::Straight)
         } else {
             strategy.step_strategy(self, &turns, &curr_tile)
@@ -221,63 +492,106 @@ impl Player {
             return false;
         };
 
-        println!("Possible turns: {:?} chose {:?}", turns, turn);
-        println!("Choice? {:?}", curr_tile.choice);
-
-        // Must use valid turns (e.g. forced) when going over choice
-        if curr_tile.choice && !turns.contains(&turn) {
-            println!("Wrong direction, fell off track!");
-            self.next_falls_off = true;
+        // Stopping before the roll is used up, a sip for every field not moved
+        if self.oriented && self.may_stop(graph, turn) && strategy.stop_strategy(self) {
+            self.steps += 1;
+            let unmoved = self.fields_left();
+            trace!("Stopped early with {} fields unmoved", unmoved);
+            self.sips.stopped_early += unmoved as u32;
+            self.finished = true;
+            return true;
         }
 
-        if (!curr_tile.forced.is_empty() || self.roll.iter().fold(0, u8::add) > 9)
-            && !turns.contains(&turn)
-        {
-            if turns.is_empty() {
+        trace!("Possible turns: {:?} chose {:?}", turns, turn);
+        trace!("Choice? {:?}", curr_tile.choice);
+
+        match graph.judge(self.old_position, self.direction, turn, turns, self.too_fast()) {
+            Verdict::Drive(t) => turn = t,
+            Verdict::WrongBranch(t) => {
+                // Down the other branch than picked for this lap, driven back from the next turn
+                trace!("Wrong branch, {:?} was picked", self.direction + t);
+                self.wrong_branch = Some(WrongBranch {
+                    fork: self.position,
+                    picked: self.direction + t,
+                    trail: vec![],
+                    reversing: false,
+                });
+            }
+            Verdict::FallsOff(t) => {
+                trace!("Wrong direction, fell off track!");
                 self.next_falls_off = true;
-                turn = Turn::Straight;
-            } else {
-                // Force valid
-                turn = turns[0];
+                turn = t;
             }
-        };
+        }
 
-        if curr_tile.blockage.contains(&(self.direction + turn)) {
-            self.next_falls_off = true;
+        // Hazardous driving: whine at every direction change, on two wheels through the turns
+        let whine = turn != Turn::Straight && self.hazardous();
+        if whine {
+            self.whines += 1;
+            self.sips.whines += 1;
+        }
+        self.on_two_wheels = whine && self.rules.two_wheels;
+        if self.on_two_wheels {
+            self.sips.two_wheels += 1;
         }
 
         self.direction = self.direction + turn;
+        if !self.oriented {
+            self.face(self.direction); // Turned without moving
+            return true;
+        }
         self.steps += 1;
         // TODO: Allow player to call finished steps themselves
-        if self.steps == self.roll.iter().fold(0, u8::add) {
+        if self.fields_left() == 0 {
             self.finished = true;
         }
 
         return true;
     }
 
-    pub fn roll_dice(&mut self, mut strategy: impl GearStrategy) {
+    pub fn roll_dice(&mut self, mut strategy: impl GearStrategy, rng: &mut impl Rng) {
         self.finished = false;
+        self.oriented = self.free_facing(); // Faced before the roll, otherwise turning on the first tile
+        if let Some(wrong) = &mut self.wrong_branch {
+            wrong.reversing = true; // The whole move goes back to the fork first
+            self.oriented = true;
+        }
 
         self.turned_over = false;
+        self.whines = 0;
+        self.on_two_wheels = false;
 
+        let gear = self.gear;
         if self.stalled {
             self.gear = 1;
             self.stalled = false;
         } else if self.forced_gear_down {
             self.gear = ChangeGear::Down.update_gear(self.gear);
             self.forced_gear_down = false;
+        } else if self.rules.stalin {
+            self.gear = ChangeGear::Up.update_gear(self.gear); // Stalin always gears up
         } else {
             self.gear = strategy.gear_strategy(&self).update_gear(self.gear);
         }
+        self.sips.gear_down += gear.saturating_sub(self.gear) as u32;
 
-        let dice_dist: Vec<_> = (1..=4_u8).into_iter().collect();
-        let mut rng = rand::rng();
+        // Faces above `dice_faces` are rolled again, a technical fault
+        // for the third in a turn and every one after it
+        let faces = self.rules.dice_faces;
+        let mut rerolls: u32 = 0;
         self.roll = (0..self.gear)
-            .map(|_| *dice_dist.choose(&mut rng).unwrap())
+            .map(|_| loop {
+                let face = rng.random_range(1..=6);
+                if face <= faces {
+                    break face;
+                }
+                rerolls += 1;
+            })
             .collect();
+        self.sips.faults += rerolls.saturating_sub(2);
         self.steps = 0; // self.roll.iter().fold(0, u8::add);
-        println!("Roll: {:?}", self.roll);
+        self.boost = 0;
+        trace!("Roll: {:?}", self.roll);
     }
 
     // nøl - hesitation / dither
@@ -0,0 +1,299 @@
+use crate::canvas_draw::*;
+use crate::effects::*;
+use crate::export::*;
+use crate::map::*;
+use crate::util::*;
+use sdl2::pixels::Color;
+use sdl2::rect::Point;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+// Boards at real-world size for playing with bottle caps, tiled over paper pages
+
+const UNITS_PER_MM: f64 = 10.0; // SVG units of the pages
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Paper {
+    A4,
+    Letter,
+}
+
+impl Paper {
+    /// Portrait width and height in millimetres
+    pub fn size(self) -> (f64, f64) {
+        match self {
+            Paper::A4 => (210.0, 297.0),
+            Paper::Letter => (215.9, 279.4),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "a4" => Some(Paper::A4),
+            "letter" => Some(Paper::Letter),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PrintLayout {
+    pub paper: Paper,
+    pub hex_mm: f64,     // Width of a hex across the flat sides, a bit more than a bottle cap
+    pub margin_mm: f64,  // Border of each page left for labels and marks
+    pub overlap_mm: f64, // Board printed on both of two neighbouring pages
+}
+
+impl Default for PrintLayout {
+    fn default() -> Self {
+        PrintLayout {
+            paper: Paper::A4,
+            hex_mm: 32.0,
+            margin_mm: 10.0,
+            overlap_mm: 15.0,
+        }
+    }
+}
+
+pub struct Page {
+    pub label: String, // Row letter and column number, "legend" for the legend
+    pub svg: Svg,
+}
+
+// Moves everything drawn, so every page cuts from the same picture of the board
+struct Offset<'a, P: Painter> {
+    canvas: &'a mut P,
+    dx: i32,
+    dy: i32,
+}
+
+impl<P: Painter> Painter for Offset<'_, P> {
+    fn set_draw_color(&mut self, color: Color) {
+        self.canvas.set_draw_color(color);
+    }
+
+    fn draw_line<P1: Into<Point>, P2: Into<Point>>(&mut self, start: P1, end: P2) -> Result<(), String> {
+        let (start, end): (Point, Point) = (start.into(), end.into());
+        self.canvas.draw_line(start.offset(self.dx, self.dy), end.offset(self.dx, self.dy))
+    }
+
+    fn draw_lines(&mut self, points: &[Point]) -> Result<(), String> {
+        let points: Vec<Point> = points.iter().map(|p| p.offset(self.dx, self.dy)).collect();
+        self.canvas.draw_lines(&points[..])
+    }
+
+    fn fill_hexagon(&mut self, x: i32, y: i32, scale: f64) {
+        self.canvas.fill_hexagon(x + self.dx, y + self.dy, scale);
+    }
+}
+
+fn mm(v: f64) -> i32 {
+    (v * UNITS_PER_MM).round() as i32
+}
+
+// Letters of a row like spreadsheet columns: A to Z, then AA, AB and on
+fn row_label(row: i32) -> String {
+    let mut label = String::new();
+    let mut n = row + 1;
+    while n > 0 {
+        n -= 1;
+        label.insert(0, (b'A' + (n % 26) as u8) as char);
+        n /= 26;
+    }
+    label
+}
+
+fn page_label(row: i32, column: i32) -> String {
+    format!("{}{}", row_label(row), column + 1)
+}
+
+fn blank_page(paper: Paper) -> Svg {
+    let (width, height) = paper.size();
+    let mut svg = Svg::new(mm(width) as u32, mm(height) as u32);
+    svg.millimetres = Some((width, height));
+    svg.clear(Color::RGB(255, 255, 255));
+    svg
+}
+
+/// Pages of the board, row by row, followed by a legend of the tile types.
+/// Pages without any part of the board are left out.
+pub fn print_pages(name: &str, map: &HexMap, layout: &PrintLayout) -> Vec<Page> {
+    let (paper_width, paper_height) = layout.paper.size();
+    let scale = layout.hex_mm / 3_f64.sqrt() * UNITS_PER_MM;
+    let frame = Frame::fit(map, scale);
+
+    let margin = mm(layout.margin_mm);
+    let overlap = mm(layout.overlap_mm);
+    let (area_width, area_height) = (mm(paper_width) - 2 * margin, mm(paper_height) - 2 * margin);
+    let (step_x, step_y) = (area_width - overlap, area_height - overlap);
+    let columns = ((frame.width as i32 - overlap).max(1) + step_x - 1) / step_x;
+    let rows = ((frame.height as i32 - overlap).max(1) + step_y - 1) / step_y;
+
+    let centers: Vec<Coord> = map.tiles.keys().map(|c| frame.start + *c * (scale as i32)).collect();
+    let used = |row: i32, column: i32| {
+        let (left, top) = (column * step_x, row * step_y);
+        let r = scale as i32;
+        centers.iter().any(|c| {
+            c.x() + r > left && c.x() - r < left + area_width && c.y() + r > top && c.y() - r < top + area_height
+        })
+    };
+
+    let text = margin as f64 * 0.4;
+    let mut pages = vec![];
+    for row in 0..rows {
+        for column in 0..columns {
+            if !used(row, column) {
+                continue;
+            }
+            let label = page_label(row, column);
+            let mut svg = blank_page(layout.paper);
+
+            svg.elements.push(format!(
+                "<clipPath id=\"area\"><rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/></clipPath>",
+                margin, margin, area_width, area_height
+            ));
+            svg.elements.push("<g clip-path=\"url(#area)\" stroke-width=\"2\">".to_string());
+            map.draw(
+                &mut Offset {
+                    canvas: &mut svg,
+                    dx: margin - column * step_x,
+                    dy: margin - row * step_y,
+                },
+                frame.start,
+                frame.scale,
+            );
+            svg.elements.push("</g>".to_string());
+
+            // Overlap marks, where the neighbouring pages start and end
+            let (width, height) = (svg.width as i32, svg.height as i32);
+            svg.set_draw_color(Color::RGB(150, 150, 150));
+            if column > 0 {
+                let _ = svg.draw_line((margin + overlap, 0), (margin + overlap, height));
+            }
+            if column + 1 < columns {
+                let _ = svg.draw_line((margin + step_x, 0), (margin + step_x, height));
+            }
+            if row > 0 {
+                let _ = svg.draw_line((0, margin + overlap), (width, margin + overlap));
+            }
+            if row + 1 < rows {
+                let _ = svg.draw_line((0, margin + step_y), (width, margin + step_y));
+            }
+
+            // Coordinates for assembly
+            svg.set_draw_color(Color::RGB(0, 0, 0));
+            let title = format!("{} {}  row {}/{}  column {}/{}", label, name, row + 1, rows, column + 1, columns);
+            draw_text(&mut svg, &title, margin, (margin as f64 * 0.3) as i32, text);
+            let below = height - margin + (margin as f64 * 0.3) as i32;
+            if row > 0 {
+                let up = format!("up {}", page_label(row - 1, column));
+                draw_text(&mut svg, &up, width - margin - text_width(&up, text), (margin as f64 * 0.3) as i32, text);
+            }
+            if row + 1 < rows {
+                let down = format!("down {}", page_label(row + 1, column));
+                draw_text(&mut svg, &down, (width - text_width(&down, text)) / 2, below, text);
+            }
+            if column > 0 {
+                draw_text(&mut svg, &format!("left {}", page_label(row, column - 1)), 0, height / 2, text);
+            }
+            if column + 1 < columns {
+                let right = format!("right {}", page_label(row, column + 1));
+                draw_text(&mut svg, &right, width - text_width(&right, text), height / 2, text);
+            }
+
+            pages.push(Page { label, svg });
+        }
+    }
+
+    let pages_used = pages.len();
+    pages.push(Page {
+        label: "legend".to_string(),
+        svg: legend(name, layout, scale, rows, columns, pages_used),
+    });
+    pages
+}
+
+fn legend(name: &str, layout: &PrintLayout, scale: f64, rows: i32, columns: i32, pages: usize) -> Svg {
+    let mut svg = blank_page(layout.paper);
+    let margin = mm(layout.margin_mm);
+    let text = mm(5.0) as f64;
+    let label_text = mm(4.0) as f64;
+    let line = (text * 1.6) as i32;
+
+    svg.set_draw_color(Color::RGB(0, 0, 0));
+    let info = [
+        format!("Legend {}", name),
+        format!("Hex {} mm across, {} mm overlap", layout.hex_mm, layout.overlap_mm),
+        format!("{} of {}x{} pages, rows A-{} and columns 1-{}", pages, rows, columns, row_label(rows - 1), columns),
+        "Glue each page on top of the grey marks".to_string(),
+    ];
+    for (i, s) in info.iter().enumerate() {
+        draw_text(&mut svg, s, margin, margin + i as i32 * line, text);
+    }
+
+    let samples = [
+        ("Road", Tile::new(vec![Direction::U])),
+        ("Start field", Tile::new(vec![Direction::U]).start()),
+        ("Blue: gear down", Tile::new(vec![Direction::U]).blue()),
+        ("Pit lane: first gear", Tile::new(vec![Direction::U]).pit()),
+        ("42: name a drinker", Tile::new(vec![Direction::U]).effect(TileEffect::FortyTwo)),
+        ("Boost", Tile::new(vec![Direction::U]).effect(TileEffect::Boost(1))),
+        ("Oil slick", Tile::new(vec![Direction::U]).effect(TileEffect::OilSlick)),
+        ("Chikane", Tile::new(vec![Direction::U]).chikane()),
+        ("Choice", Tile::new(vec![Direction::U, Direction::UR]).choice()),
+        ("Rotate", Tile::new(vec![Direction::U]).rotate()),
+        ("One way", Tile::new(vec![Direction::U]).oneway()),
+        (
+            "Forced direction",
+            Tile::new(vec![Direction::U, Direction::UR])
+                .forced(BTreeMap::from([(Coord::axel(0, 1), Direction::U)])),
+        ),
+        ("Blockage", Tile::new(vec![Direction::U]).blockage(vec![Direction::UR])),
+        ("Start or mid line", Tile::new(vec![Direction::U])), // Checkered side drawn below
+    ];
+
+    let top = margin + info.len() as i32 * line + scale as i32;
+    let spacing = (2.0 * scale) as i32 + line / 2;
+    let per_column = ((svg.height as i32 - margin - top) / spacing).max(1) as usize;
+    let column_width = (svg.width as i32 - 2 * margin) / samples.len().div_ceil(per_column) as i32;
+
+    svg.elements.push("<g stroke-width=\"2\">".to_string());
+    for (i, (label, tile)) in samples.iter().enumerate() {
+        let x = margin + scale as i32 + (i / per_column) as i32 * column_width;
+        let y = top + (i % per_column) as i32 * spacing;
+        let mut offset = Offset {
+            canvas: &mut svg,
+            dx: x,
+            dy: y,
+        };
+        tile.draw(&mut offset, Coord::axel(0, 0), scale);
+        if i + 1 == samples.len() {
+            // Same checkers as `HexMap::draw`
+            for j in -2..=2 {
+                draw_hexagon_side_checkerboard(
+                    &mut offset,
+                    j,
+                    0,
+                    scale,
+                    Direction::U,
+                    Color::RGB(0, 0, 0),
+                    Color::RGB(255, 255, 255),
+                );
+            }
+        }
+        svg.set_draw_color(Color::RGB(0, 0, 0));
+        draw_text(&mut svg, label, x + (scale * 1.2) as i32, y - label_text as i32 / 2, label_text);
+    }
+    svg.elements.push("</g>".to_string());
+    svg
+}
+
+/// Write the pages as `<prefix>-<label>.svg`, returns the number of files written
+pub fn save_pages(prefix: &str, name: &str, map: &HexMap, layout: &PrintLayout) -> io::Result<usize> {
+    let pages = print_pages(name, map, layout);
+    for page in &pages {
+        page.svg.save_svg(Path::new(&format!("{}-{}.svg", prefix, page.label)))?;
+    }
+    Ok(pages.len())
+}
@@ -0,0 +1,105 @@
+use crate::map::*;
+use crate::simulation::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// Deciding the grid before the race, from `Python/rules.tex`: a roll-off where
+// the highest roll starts and tied players roll again, or "tidskørsel", where
+// the fastest time starts. The ranking sets both the turn order and the grid
+// slot through `PlayerBuilder::order`.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Qualifying {
+    RollOff,
+    Times(Vec<f64>), // Entered by hand for each player, ties are rolled off
+    TimeTrial,       // A solo lap on the board for each player, ties are rolled off
+}
+
+impl Qualifying {
+    /// `rolloff`, `timetrial` or the times of each player like `12.5,9.8,14`
+    pub fn parse(spec: &str) -> Option<Self> {
+        match spec.to_ascii_lowercase().as_str() {
+            "rolloff" | "roll_off" => Some(Qualifying::RollOff),
+            "timetrial" | "time_trial" => Some(Qualifying::TimeTrial),
+            _ => spec
+                .split(',')
+                .map(|t| t.trim().parse::<f64>().ok())
+                .collect::<Option<Vec<f64>>>()
+                .map(Qualifying::Times),
+        }
+    }
+
+    /// Players of `map` from pole position backwards, each using their
+    /// strategy from `strategies` for a time trial
+    pub fn ranking(
+        &self,
+        map: &HexMap,
+        strategies: &[(PlayerGearStrategy, PlayerStepStrategy)],
+        seed: u64,
+    ) -> Vec<usize> {
+        let players = map.player_builder.total;
+        let mut rng = StdRng::seed_from_u64(seed);
+        match self {
+            Qualifying::RollOff => roll_off((0..players).collect(), &mut rng),
+            Qualifying::Times(times) => {
+                let times: Vec<f64> = (0..players).map(|i| times.get(i).copied().unwrap_or(f64::MAX)).collect();
+                fastest_first(&times, &mut rng)
+            }
+            Qualifying::TimeTrial => {
+                let times: Vec<f64> = (0..players)
+                    .map(|i| time_trial(map, strategies.get(i).copied(), seed.wrapping_add(i as u64)))
+                    .collect();
+                fastest_first(&times, &mut rng)
+            }
+        }
+    }
+}
+
+/// Put the players of `map` in the order of `ranking`, along with their `strategies`
+pub fn apply(ranking: Vec<usize>, map: &mut HexMap, strategies: &mut [(PlayerGearStrategy, PlayerStepStrategy)]) {
+    if strategies.len() >= ranking.len() {
+        let ordered: Vec<_> = ranking.iter().map(|i| strategies[*i]).collect();
+        strategies[..ordered.len()].copy_from_slice(&ordered);
+    }
+    map.player_builder.order = ranking;
+}
+
+/// Highest roll first, tied players roll again until they are apart
+pub fn roll_off(players: Vec<usize>, rng: &mut impl Rng) -> Vec<usize> {
+    if players.len() < 2 {
+        return players;
+    }
+    let rolls: Vec<(usize, u8)> = players.iter().map(|p| (*p, rng.random_range(1..=6))).collect();
+    let mut ranking = vec![];
+    for roll in (1..=6).rev() {
+        let tied: Vec<usize> = rolls.iter().filter(|(_, r)| *r == roll).map(|(p, _)| *p).collect();
+        ranking.extend(roll_off(tied, rng));
+    }
+    ranking
+}
+
+// Lowest time first, equal times rolled off
+fn fastest_first(times: &[f64], rng: &mut impl Rng) -> Vec<usize> {
+    let mut players: Vec<usize> = (0..times.len()).collect();
+    players.sort_by(|a, b| times[*a].total_cmp(&times[*b]));
+
+    let mut ranking = vec![];
+    for tied in players.chunk_by(|a, b| times[*a] == times[*b]) {
+        ranking.extend(roll_off(tied.to_vec(), rng));
+    }
+    ranking
+}
+
+// Turns for one lap alone from pole position, players who need a window drive the best line
+fn time_trial(map: &HexMap, strategy: Option<(PlayerGearStrategy, PlayerStepStrategy)>, seed: u64) -> f64 {
+    let strategy = match strategy {
+        Some(s @ (PlayerGearStrategy::Best | PlayerGearStrategy::Optimal, PlayerStepStrategy::Best | PlayerStepStrategy::Optimal)) => s,
+        _ => (PlayerGearStrategy::Best, PlayerStepStrategy::Best),
+    };
+    let mut map = map.clone();
+    map.player_builder.order = vec![];
+
+    let mut simulation = Simulation::new(map, vec![strategy]);
+    let stats = simulation.run(1, seed);
+    stats.lap_turns.first().map_or(f64::MAX, |t| *t as f64)
+}
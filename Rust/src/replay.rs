@@ -0,0 +1,203 @@
+use crate::map::*;
+use crate::player::*;
+use crate::util::*;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+// Recorded races, one snapshot of every car after each step of the engine.
+//
+// File format, one item per line:
+//   board <name>
+//   seed <seed>
+//   step <active player>
+//   car <q> <r> <direction 0-5> <gear> <roll, comma separated or -> <turned over 0/1>
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CarState {
+    pub position: Coord,
+    pub direction: Direction,
+    pub gear: u8,
+    pub roll: Vec<u8>,
+    pub turned_over: bool,
+}
+
+impl CarState {
+    pub fn of(player: &Player) -> Self {
+        CarState {
+            position: player.position,
+            direction: player.direction,
+            gear: player.gear,
+            roll: player.roll.clone(),
+            turned_over: player.turned_over,
+        }
+    }
+
+    pub fn apply(&self, player: &mut Player) {
+        player.position = self.position;
+        player.direction = self.direction;
+        player.gear = self.gear;
+        player.roll = self.roll.clone();
+        player.turned_over = self.turned_over;
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ReplayStep {
+    pub player: usize, // Player whose turn it is
+    pub cars: Vec<CarState>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Replay {
+    pub board: String,
+    pub seed: u64,
+    pub steps: Vec<ReplayStep>,
+}
+
+impl Replay {
+    pub fn new(board: &str, seed: u64) -> Self {
+        Replay {
+            board: board.to_string(),
+            seed,
+            steps: vec![],
+        }
+    }
+
+    /// Snapshot the game, skipped when nothing changed since the last step
+    pub fn record(&mut self, game: &GameState) {
+        let step = ReplayStep {
+            player: game.player_index,
+            cars: game.players.iter().map(CarState::of).collect(),
+        };
+        if self.steps.last() != Some(&step) {
+            self.steps.push(step);
+        }
+    }
+
+    /// Headless race with `PlayerGearStrategy::Best` in every seat, until all
+    /// players finished `laps` laps or used `max_turns` turns
+    pub fn race(board: &str, map: HexMap, seed: u64, laps: usize, max_turns: usize) -> Self {
+        let was_verbose = verbose();
+        set_verbose(false);
+
+        let strategies = vec![(PlayerGearStrategy::Best, PlayerStepStrategy::Best); map.player_builder.total];
+        let mut game = GameState::headless(map, strategies, seed);
+        let mut replay = Replay::new(board, seed);
+        replay.record(&game);
+
+        let players = game.players.len();
+        let mut turns = vec![0; players];
+        let done = |game: &GameState, turns: &Vec<usize>, i: usize| game.players[i].round > laps || turns[i] >= max_turns;
+
+        while (0..players).any(|i| !done(&game, &turns, i)) {
+            let i = game.player_index;
+            if done(&game, &turns, i) && game.rolling {
+                // Skip players that are done
+                game.player_index = (i + 1) % players;
+                game.update_paths();
+                continue;
+            }
+            if game.rolling {
+                turns[i] += 1;
+            }
+            game.step_game(None);
+            replay.record(&game);
+        }
+
+        set_verbose(was_verbose);
+        replay
+    }
+
+    /// Players and their trails after each step
+    pub fn frames(&self, map: &HexMap) -> Vec<(usize, Vec<Player>, Vec<Vec<Coord>>)> {
+        let mut builder = map.player_builder.clone();
+        builder.total = builder.total.min(builder.placements.len());
+        let mut players = builder.all_players();
+        let mut trails: Vec<Vec<Coord>> = vec![vec![]; players.len()];
+
+        let mut frames = vec![];
+        for step in &self.steps {
+            players.truncate(step.cars.len());
+            for (i, (p, car)) in players.iter_mut().zip(&step.cars).enumerate() {
+                car.apply(p);
+                if trails[i].last() != Some(&car.position) {
+                    trails[i].push(car.position);
+                }
+            }
+            frames.push((step.player, players.clone(), trails.clone()));
+        }
+        frames
+    }
+
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "board {}", self.board)?;
+        writeln!(writer, "seed {}", self.seed)?;
+        for step in &self.steps {
+            writeln!(writer, "step {}", step.player)?;
+            for car in &step.cars {
+                let roll: Vec<String> = car.roll.iter().map(|r| r.to_string()).collect();
+                writeln!(
+                    writer,
+                    "car {} {} {} {} {} {}",
+                    car.position.q,
+                    car.position.r,
+                    car.direction.to_num(),
+                    car.gear,
+                    if roll.is_empty() { "-".to_string() } else { roll.join(",") },
+                    car.turned_over as u8
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read(reader: impl BufRead) -> io::Result<Self> {
+        let invalid = |line: usize| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid replay at line {}", line + 1));
+
+        let mut replay = Replay::new("", 0);
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => {}
+                ["board", name] => replay.board = name.to_string(),
+                ["seed", seed] => replay.seed = seed.parse().map_err(|_| invalid(n))?,
+                ["step", player] => replay.steps.push(ReplayStep {
+                    player: player.parse().map_err(|_| invalid(n))?,
+                    cars: vec![],
+                }),
+                ["car", q, r, d, gear, roll, over] => {
+                    let num = |s: &str| s.parse::<i32>().map_err(|_| invalid(n));
+                    let direction = num(d)?;
+                    if !(0..6).contains(&direction) {
+                        return Err(invalid(n));
+                    }
+                    let roll = if *roll == "-" {
+                        vec![]
+                    } else {
+                        roll.split(',').map(|r| r.parse::<u8>().map_err(|_| invalid(n))).collect::<io::Result<Vec<u8>>>()?
+                    };
+                    let car = CarState {
+                        position: Coord::axel(num(q)?, num(r)?),
+                        direction: Direction::from_num(direction as u8),
+                        gear: num(gear)? as u8,
+                        roll,
+                        turned_over: num(over)? != 0,
+                    };
+                    replay.steps.last_mut().ok_or_else(|| invalid(n))?.cars.push(car);
+                }
+                _ => return Err(invalid(n)),
+            }
+        }
+        Ok(replay)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        self.write(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Replay::read(BufReader::new(File::open(path)?))
+    }
+}
@@ -0,0 +1,255 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+// Rule variants from `Python/rules.tex`
+//
+// Rule set files, one setting per line, `#` starts a comment:
+//   preset <babyracerbile|racerbile|lemans>
+//   name <name>
+//   <setting> <value>, as written by `RuleSet::write`
+// Settings not given keep the value of the preset (Racerbile by default),
+// which therefore comes first. Le Mans settings turn on the Le Mans rules.
+
+/// How a car is oriented at the start of a turn
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub enum Variant {
+    /// Caps have no facing, the first move of a turn may leave in any direction
+    /// the arrows of the tile allow
+    Babyracerbile,
+    /// The car keeps facing the direction it last drove and may turn once the
+    /// dice are rolled, so a roll of 10 or more can leave the board at once.
+    /// The facing is chosen freely on the grid.
+    #[default]
+    Racerbile,
+}
+
+impl Variant {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "babyracerbile" | "baby" => Some(Variant::Babyracerbile),
+            "racerbile" => Some(Variant::Racerbile),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Variant::Babyracerbile => "babyracerbile",
+            Variant::Racerbile => "racerbile",
+        }
+    }
+}
+
+/// Where a player may stop before the roll is used up, one sip per field not
+/// moved. A car stopped in front of a blocked field pays the same in both.
+/// Rolls too fast to turn are always driven out.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum StopEarly {
+    Anywhere,
+    Chikane, // Racerbile: only in front of a chikane, the blocking field
+}
+
+/// What leaving the board costs before reversing back onto it in first gear
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Reentry {
+    Reverse,
+    FinishBeer, // Racerbile: the rest of the beer is finished first
+}
+
+/// Le Mans extensions on top of Racerbile
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct LeMans {
+    pub drivers: usize,         // Drivers in each team
+    pub pit_gear: u8,           // Highest gear to drive into the pit lane in
+    pub swap_beers: u32,        // Beers the incoming driver finishes to swap outside the pit
+    pub max_sips_per_turn: u32, // Most sips one turn can cost
+}
+
+impl Default for LeMans {
+    fn default() -> Self {
+        LeMans {
+            drivers: 3,
+            pit_gear: 1,
+            swap_beers: 1,
+            max_sips_per_turn: 42,
+        }
+    }
+}
+
+/// Rules the engine plays by
+#[derive(Clone, PartialEq, Debug)]
+pub struct RuleSet {
+    pub name: String,
+    pub variant: Variant,
+    pub dice_faces: u8,         // Faces counted on each die, higher faces are rolled again
+    pub turn_limit: u8,         // Highest roll the car can still turn with
+    pub whine_from: u8,         // Lowest roll the wheels whine at when turning
+    pub stop_early: StopEarly,
+    pub stack_limit: usize,     // Cars on a field before it blocks the cars behind
+    pub reentry: Reentry,
+
+    // Special rules
+    pub two_wheels: bool,           // Hazardous driving turns on two wheels
+    pub out_of_fuel: Option<usize>, // Lead in fields at the start of a turn that empties the tank
+    pub stalin: bool,               // Always gear up
+
+    pub le_mans: Option<LeMans>,
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        RuleSet::racerbile()
+    }
+}
+
+impl RuleSet {
+    pub fn babyracerbile() -> Self {
+        RuleSet {
+            name: "babyracerbile".to_string(),
+            variant: Variant::Babyracerbile,
+            dice_faces: 4,
+            turn_limit: 9,
+            whine_from: 7,
+            stop_early: StopEarly::Anywhere,
+            stack_limit: 2,
+            reentry: Reentry::Reverse,
+            two_wheels: false,
+            out_of_fuel: None,
+            stalin: false,
+            le_mans: None,
+        }
+    }
+
+    pub fn racerbile() -> Self {
+        RuleSet {
+            name: "racerbile".to_string(),
+            variant: Variant::Racerbile,
+            stop_early: StopEarly::Chikane,
+            reentry: Reentry::FinishBeer,
+            ..RuleSet::babyracerbile()
+        }
+    }
+
+    pub fn le_mans() -> Self {
+        RuleSet {
+            name: "lemans".to_string(),
+            le_mans: Some(LeMans::default()),
+            ..RuleSet::racerbile()
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "babyracerbile" | "baby" => Some(RuleSet::babyracerbile()),
+            "racerbile" => Some(RuleSet::racerbile()),
+            "lemans" | "le_mans" => Some(RuleSet::le_mans()),
+            _ => None,
+        }
+    }
+
+    /// Preset called `spec`, otherwise the rule set file at that path
+    pub fn find(spec: &str) -> io::Result<Self> {
+        match RuleSet::from_name(spec) {
+            Some(rules) => Ok(rules),
+            None => RuleSet::load(Path::new(spec)),
+        }
+    }
+
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        let on_off = |b: bool| if b { "on" } else { "off" };
+        writeln!(writer, "name {}", self.name)?;
+        writeln!(writer, "variant {}", self.variant.name())?;
+        writeln!(writer, "dice_faces {}", self.dice_faces)?;
+        writeln!(writer, "turn_limit {}", self.turn_limit)?;
+        writeln!(writer, "whine_from {}", self.whine_from)?;
+        writeln!(writer, "stop_early {}", match self.stop_early {
+            StopEarly::Anywhere => "anywhere",
+            StopEarly::Chikane => "chikane",
+        })?;
+        writeln!(writer, "stack_limit {}", self.stack_limit)?;
+        writeln!(writer, "reentry {}", match self.reentry {
+            Reentry::Reverse => "reverse",
+            Reentry::FinishBeer => "finish_beer",
+        })?;
+        writeln!(writer, "two_wheels {}", on_off(self.two_wheels))?;
+        match self.out_of_fuel {
+            Some(lead) => writeln!(writer, "out_of_fuel {}", lead)?,
+            None => writeln!(writer, "out_of_fuel off")?,
+        }
+        writeln!(writer, "stalin {}", on_off(self.stalin))?;
+        writeln!(writer, "le_mans {}", on_off(self.le_mans.is_some()))?;
+        if let Some(le_mans) = &self.le_mans {
+            writeln!(writer, "drivers {}", le_mans.drivers)?;
+            writeln!(writer, "pit_gear {}", le_mans.pit_gear)?;
+            writeln!(writer, "swap_beers {}", le_mans.swap_beers)?;
+            writeln!(writer, "max_sips_per_turn {}", le_mans.max_sips_per_turn)?;
+        }
+        Ok(())
+    }
+
+    pub fn read(reader: impl BufRead) -> io::Result<Self> {
+        let invalid = |line: usize| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid rule set at line {}", line + 1));
+
+        let mut rules = RuleSet::default();
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.split('#').next().unwrap_or_default();
+            let Some((key, value)) = line.trim().split_once(char::is_whitespace) else {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                return Err(invalid(n));
+            };
+            let value = value.trim();
+            let number = || value.parse::<usize>().map_err(|_| invalid(n));
+            let on_off = || match value {
+                "on" => Ok(true),
+                "off" => Ok(false),
+                _ => Err(invalid(n)),
+            };
+
+            match key {
+                "preset" => rules = RuleSet::from_name(value).ok_or_else(|| invalid(n))?,
+                "name" => rules.name = value.to_string(),
+                "variant" => rules.variant = Variant::from_name(value).ok_or_else(|| invalid(n))?,
+                "dice_faces" => rules.dice_faces = number()?.clamp(1, 6) as u8,
+                "turn_limit" => rules.turn_limit = number()? as u8,
+                "whine_from" => rules.whine_from = number()? as u8,
+                "stop_early" => {
+                    rules.stop_early = match value {
+                        "anywhere" => StopEarly::Anywhere,
+                        "chikane" => StopEarly::Chikane,
+                        _ => return Err(invalid(n)),
+                    }
+                }
+                "stack_limit" => rules.stack_limit = number()?.max(1),
+                "reentry" => {
+                    rules.reentry = match value {
+                        "reverse" => Reentry::Reverse,
+                        "finish_beer" => Reentry::FinishBeer,
+                        _ => return Err(invalid(n)),
+                    }
+                }
+                "two_wheels" => rules.two_wheels = on_off()?,
+                "out_of_fuel" => rules.out_of_fuel = if value == "off" { None } else { Some(number()?) },
+                "stalin" => rules.stalin = on_off()?,
+                "le_mans" => rules.le_mans = on_off()?.then(|| rules.le_mans.clone().unwrap_or_default()),
+                "drivers" => rules.le_mans.get_or_insert_default().drivers = number()?.max(1),
+                "pit_gear" => rules.le_mans.get_or_insert_default().pit_gear = number()?.clamp(1, 3) as u8,
+                "swap_beers" => rules.le_mans.get_or_insert_default().swap_beers = number()? as u32,
+                "max_sips_per_turn" => rules.le_mans.get_or_insert_default().max_sips_per_turn = number()? as u32,
+                _ => return Err(invalid(n)),
+            }
+        }
+        Ok(rules)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        self.write(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        RuleSet::read(BufReader::new(File::open(path)?))
+    }
+}
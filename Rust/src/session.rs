@@ -0,0 +1,77 @@
+use crate::trace;
+use std::time::{Duration, Instant};
+
+// Races ending on a clock instead of a lap count, as in Le Mans: once the
+// clock runs out "last lap" is called, and the race ends when the car last in
+// the turn order, the one with the worst qualifying, has had its turn.
+
+/// What the session clock counts
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SessionClock {
+    Turns(usize), // Rounds of turns, every car once
+    Time(Duration),
+}
+
+impl SessionClock {
+    /// `<rounds>` or `<minutes>m`
+    pub fn parse(spec: &str) -> Option<Self> {
+        match spec.strip_suffix('m') {
+            Some(minutes) => Some(SessionClock::Time(Duration::from_secs(minutes.parse::<u64>().ok()? * 60))),
+            None => Some(SessionClock::Turns(spec.parse().ok()?)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub clock: Option<SessionClock>, // None for races over a lap count
+    pub rounds: usize,               // Rounds of turns started
+    pub started: Instant,
+    pub last_lap: bool,
+    pub over: bool,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session::new(None)
+    }
+}
+
+impl Session {
+    pub fn new(clock: Option<SessionClock>) -> Self {
+        Session {
+            clock,
+            rounds: 0,
+            started: Instant::now(),
+            last_lap: false,
+            over: false,
+        }
+    }
+
+    /// The round in progress is the last one once the clock has run out
+    pub fn expired(&self) -> bool {
+        match self.clock {
+            Some(SessionClock::Turns(rounds)) => self.rounds >= rounds,
+            Some(SessionClock::Time(limit)) => self.started.elapsed() >= limit,
+            None => false,
+        }
+    }
+
+    /// Before the turn of the car at `index` in the turn order
+    pub fn start_turn(&mut self, index: usize) {
+        if index == 0 {
+            self.rounds += 1;
+        }
+        if !self.last_lap && self.expired() {
+            self.last_lap = true;
+            trace!("Last lap");
+        }
+    }
+
+    /// After the turn of the car at `index` of `cars`
+    pub fn end_turn(&mut self, index: usize, cars: usize) {
+        if self.last_lap && index + 1 == cars {
+            self.over = true;
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use crate::map::*;
+use crate::solver::*;
+use crate::util::*;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+// Batches of headless games, aggregated per hex
+
+#[derive(Clone, Default, Debug)]
+pub struct HexStats {
+    pub visits: usize,
+    pub crashes: usize, // Left the board from this hex
+    pub bonks: usize,   // Blocked from entering this hex
+    pub gear_total: usize, // Sum of the gear of each visit
+}
+
+#[derive(Clone, Default)]
+pub struct SimulationStats {
+    pub games: usize,
+    pub hexes: BTreeMap<Coord, HexStats>,
+    pub gear_hexes: BTreeMap<(Coord, u8), HexStats>,
+    pub lap_turns: Vec<usize>, // Turns used for each completed lap
+    pub unfinished: usize,     // Players that ran out of turns
+}
+
+impl SimulationStats {
+    pub fn average_lap(&self) -> Option<f64> {
+        if self.lap_turns.is_empty() {
+            None
+        } else {
+            Some(self.lap_turns.iter().sum::<usize>() as f64 / self.lap_turns.len() as f64)
+        }
+    }
+
+    /// Hexes with the highest share of visits in `gear` ending off the board
+    pub fn crash_risk(&self, gear: u8, min_visits: usize) -> Vec<(Coord, f64)> {
+        let mut risk: Vec<(Coord, f64)> = self
+            .gear_hexes
+            .iter()
+            .filter(|((_, g), h)| *g == gear && h.visits >= min_visits && h.crashes > 0)
+            .map(|((c, _), h)| (*c, h.crashes as f64 / h.visits as f64))
+            .collect();
+        risk.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        risk
+    }
+}
+
+pub struct Simulation {
+    pub map: HexMap,
+    pub strategies: Vec<(PlayerGearStrategy, PlayerStepStrategy)>,
+    pub laps: usize,
+    pub max_turns: usize, // Per player and game
+    pub solver: Option<Arc<Solver>>, // Shared between games
+}
+
+impl Simulation {
+    pub fn new(
+        mut map: HexMap,
+        strategies: Vec<(PlayerGearStrategy, PlayerStepStrategy)>,
+    ) -> Self {
+        assert!(
+            strategies.len() <= map.player_builder.placements.len(),
+            "{} strategies for a board with {} grid slots",
+            strategies.len(),
+            map.player_builder.placements.len()
+        );
+        map.player_builder.total = strategies.len();
+        Simulation {
+            map,
+            strategies,
+            laps: 1,
+            max_turns: 200,
+            solver: None,
+        }
+    }
+
+    pub fn run(&mut self, games: usize, seed: u64) -> SimulationStats {
+        let was_verbose = verbose();
+        set_verbose(false);
+
+        let mut stats = SimulationStats::default();
+        for g in 0..games {
+            self.run_game(seed.wrapping_add(g as u64), &mut stats);
+        }
+
+        set_verbose(was_verbose);
+        stats
+    }
+
+    fn run_game(&mut self, seed: u64, stats: &mut SimulationStats) {
+        let mut game = GameState::headless(self.map.clone(), self.strategies.clone(), seed);
+        game.solver = self.solver.clone();
+
+        let players = game.players.len();
+        let mut turns = vec![0; players];
+        let mut lap_start = vec![0; players];
+
+        let done = |game: &GameState, turns: &Vec<usize>, i: usize| {
+            game.players[i].round > self.laps || turns[i] >= self.max_turns
+        };
+
+        while (0..players).any(|i| !done(&game, &turns, i)) {
+            let i = game.player_index;
+            if done(&game, &turns, i) {
+                // Skip players that are done
+                game.player_index = (i + 1) % players;
+                game.rolling = true;
+                game.update_paths();
+                continue;
+            }
+
+            if game.rolling {
+                turns[i] += 1;
+            }
+            let before = game.players[i].clone();
+            game.step_game(None);
+            let p = &game.players[i];
+
+            if p.position != before.position && self.map.tiles.contains_key(&p.position) {
+                for h in [
+                    stats.hexes.entry(p.position).or_default(),
+                    stats.gear_hexes.entry((p.position, p.gear)).or_default(),
+                ] {
+                    h.visits += 1;
+                    h.gear_total += p.gear as usize;
+                }
+            }
+            if p.outside_board() && !before.outside_board() {
+                stats.hexes.entry(p.old_position).or_default().crashes += 1;
+                stats.gear_hexes.entry((p.old_position, p.gear)).or_default().crashes += 1;
+            }
+            if p.bonked && !before.bonked {
+                let blocked = p.position + p.direction.to_coord();
+                stats.hexes.entry(blocked).or_default().bonks += 1;
+                stats.gear_hexes.entry((blocked, p.gear)).or_default().bonks += 1;
+            }
+            if p.round > before.round {
+                stats.lap_turns.push(turns[i] - lap_start[i]);
+                lap_start[i] = turns[i];
+            }
+        }
+
+        stats.games += 1;
+        stats.unfinished += (0..players).filter(|i| game.players[*i].round <= self.laps).count();
+        if self.solver.is_none() {
+            self.solver = game.solver.take();
+        }
+    }
+}
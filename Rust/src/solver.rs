@@ -0,0 +1,520 @@
+use crate::choice::*;
+use crate::graph::*;
+use crate::map::*;
+use crate::player::*;
+use crate::rules::*;
+use crate::util::*;
+use std::collections::BTreeMap;
+
+// Value iteration for single player time trials. Moves are simulated with
+// `Player::pre_step` and `Player::step`, so the solver follows the engine rules.
+// Stopping early is one more choice inside a move where the rule set allows it
+// (see `StopEarly`). Its sips are not counted, only the turns to finish the lap.
+
+#[derive(Copy, Clone, PartialOrd, Ord, Eq, PartialEq, Debug)]
+pub struct SolverState {
+    pub position: Coord,
+    pub direction: Direction,
+    pub gear: u8,
+    pub stalled: bool,
+    pub forced_gear_down: bool,
+    pub first_half: bool,
+}
+
+// Point inside a move where the player picks a turn (after `pre_step`)
+#[derive(Copy, Clone, PartialOrd, Ord, Eq, PartialEq, Debug)]
+pub struct DecisionKey {
+    pub position: Coord,
+    pub old_position: Coord,
+    pub direction: Direction,
+    pub steps: u8,
+    pub roll: u8,
+    pub boost: u8,
+    pub gear: u8,
+    pub first_half: bool,
+    pub oriented: bool, // Otherwise turning without moving at the start of the turn
+    pub next_falls_off: bool,
+}
+
+impl DecisionKey {
+    fn from_player(player: &Player) -> Self {
+        DecisionKey {
+            position: player.position,
+            old_position: player.old_position,
+            direction: player.direction,
+            steps: player.steps,
+            roll: player.roll.iter().sum(),
+            boost: player.boost,
+            gear: player.gear,
+            first_half: player.first_half,
+            oriented: player.oriented,
+            next_falls_off: player.next_falls_off,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Outcome {
+    Finished,
+    State(usize),
+    Random(Vec<usize>), // Equally likely states (rotate tiles)
+    Choice(Vec<usize>), // States the player picks between (facing when back on the board)
+    Delayed(Box<Outcome>), // Outcome after one more turn (`next_falls_off`)
+}
+
+#[derive(Clone, Debug)]
+enum Child {
+    Node(usize),
+    Outcome(Outcome),
+}
+
+const TURNS: [Turn; 3] = [Turn::Left, Turn::Straight, Turn::Right];
+const GEAR_CHANGES: [ChangeGear; 3] = [ChangeGear::Down, ChangeGear::Stay, ChangeGear::Up];
+
+struct ScriptedStep(Turn, bool); // Turn taken, stopping early instead
+
+impl StepStrategy for ScriptedStep {
+    fn step_strategy(&mut self, _: &Player, _: &Vec<Turn>, _: &Tile) -> Option<Turn> {
+        Some(self.0)
+    }
+
+    fn stop_strategy(&mut self, _: &Player) -> bool {
+        self.1
+    }
+}
+
+pub struct Solver {
+    pub map: HexMap,
+    pub graph: TrackGraph,
+    pub states: Vec<SolverState>,
+    pub index: BTreeMap<SolverState, usize>,
+    pub values: Vec<f64>, // Expected number of turns to finish the lap
+    pub gears: Vec<ChangeGear>,
+    pub iterations: usize,
+
+    decisions: BTreeMap<DecisionKey, usize>,
+    nodes: Vec<Vec<(Turn, bool, Child)>>, // Children for each turn in `TURNS`, then for stopping where allowed, in post order
+    node_values: Vec<f64>,
+    roots: Vec<[Vec<(f64, Child)>; 3]>, // Roll probability and start of move for each gear change
+}
+
+/// Probability of each sum when rolling `dice` dice with faces 1 to `faces`
+pub fn roll_distribution(dice: u8, faces: u8) -> Vec<(u8, f64)> {
+    let mut dist: BTreeMap<u8, f64> = BTreeMap::from([(0, 1.0)]);
+    for _ in 0..dice {
+        let mut next = BTreeMap::new();
+        for (sum, p) in dist {
+            for face in 1..=faces {
+                *next.entry(sum + face).or_insert(0.0) += p / faces as f64;
+            }
+        }
+        dist = next;
+    }
+    dist.into_iter().collect()
+}
+
+impl Solver {
+    pub fn new(map: HexMap) -> Self {
+        let mut states = vec![];
+        for c in map.tiles.keys() {
+            for d in 0..6 {
+                for gear in 1..=3 {
+                    for stalled in [false, true] {
+                        for forced_gear_down in [false, true] {
+                            for first_half in [true, false] {
+                                states.push(SolverState {
+                                    position: *c,
+                                    direction: Direction::from_num(d),
+                                    gear,
+                                    stalled,
+                                    forced_gear_down,
+                                    first_half,
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        let index = states.iter().enumerate().map(|(i, s)| (*s, i)).collect();
+
+        let mut solver = Solver {
+            graph: map.graph(),
+            map,
+            values: vec![0.0; states.len()],
+            gears: vec![ChangeGear::Stay; states.len()],
+            states,
+            index,
+            iterations: 0,
+            decisions: BTreeMap::new(),
+            nodes: vec![],
+            node_values: vec![],
+            roots: vec![],
+        };
+
+        let was_verbose = verbose();
+        set_verbose(false);
+        solver.roots = (0..solver.states.len())
+            .map(|s| GEAR_CHANGES.map(|change| solver.build_roots(s, change)))
+            .collect();
+        set_verbose(was_verbose);
+        solver.node_values = vec![0.0; solver.nodes.len()];
+
+        solver
+    }
+
+    pub fn solve(map: HexMap) -> Self {
+        let mut solver = Solver::new(map);
+        solver.iterate(1e-6, 10_000);
+        solver
+    }
+
+    fn player(&self, s: &SolverState) -> Player {
+        let mut player = self.map.player_builder.clone().next_player();
+        player.position = s.position;
+        player.old_position = s.position;
+        player.direction = s.direction;
+        player.gear = s.gear;
+        player.stalled = s.stalled;
+        player.forced_gear_down = s.forced_gear_down;
+        player.first_half = s.first_half;
+        player.on_grid = false;
+        player
+    }
+
+    fn build_roots(&mut self, s: usize, change: ChangeGear) -> Vec<(f64, Child)> {
+        let state = self.states[s];
+        let gear = if state.stalled {
+            1
+        } else if state.forced_gear_down {
+            ChangeGear::Down.update_gear(state.gear)
+        } else {
+            change.update_gear(state.gear)
+        };
+
+        roll_distribution(gear, self.map.player_builder.rules.dice_faces)
+            .into_iter()
+            .map(|(roll, p)| {
+                let mut player = self.player(&state);
+                player.gear = gear;
+                player.stalled = false;
+                player.forced_gear_down = false;
+                player.roll = vec![roll];
+                player.steps = 0;
+                player.boost = 0;
+                player.finished = false;
+                player.oriented = player.free_facing(); // Facing chosen in `landing`, as before the roll
+                (p, self.advance(player))
+            })
+            .collect()
+    }
+
+    // Move until the next decision or the end of the move
+    fn advance(&mut self, mut player: Player) -> Child {
+        let no_blockages = vec![];
+        let no_choices = ChoiceLocks::default();
+        loop {
+            let old_dir = player.direction;
+            let round = player.round;
+            if let Some(turns) = player.pre_step(&self.graph, &no_blockages, &no_choices) {
+                return Child::Node(self.decision(player, turns));
+            }
+
+            self.map.update_lap(&mut player, old_dir);
+            if player.round > round {
+                return Child::Outcome(Outcome::Finished);
+            }
+            if player.finished {
+                return Child::Outcome(self.landing(&player));
+            }
+        }
+    }
+
+    fn decision(&mut self, player: Player, turns: Vec<Turn>) -> usize {
+        let key = DecisionKey::from_player(&player);
+        if let Some(node) = self.decisions.get(&key) {
+            return *node;
+        }
+
+        let stops = TURNS.into_iter().filter(|turn| player.oriented && player.may_stop(&self.graph, *turn));
+        let actions: Vec<(Turn, bool)> = TURNS.map(|turn| (turn, false)).into_iter().chain(stops.map(|turn| (turn, true))).collect();
+        let children = actions
+            .into_iter()
+            .map(|(turn, stop)| {
+                let mut next = player.clone();
+                let old_dir = next.direction;
+                let round = next.round;
+                next.step(&turns, &self.graph, &mut ScriptedStep(turn, stop));
+                self.map.update_lap(&mut next, old_dir);
+                let child = if next.round > round {
+                    Child::Outcome(Outcome::Finished)
+                } else if next.finished {
+                    Child::Outcome(self.landing(&next))
+                } else {
+                    self.advance(next)
+                };
+                (turn, stop, child)
+            })
+            .collect();
+
+        let node = self.nodes.len();
+        self.nodes.push(children);
+        self.decisions.insert(key, node);
+        node
+    }
+
+    // State at the start of the next turn, mirrors the end of turn in `GameState::step_game`
+    fn landing(&self, player: &Player) -> Outcome {
+        if player.next_falls_off {
+            // Falls off in `pre_step` as soon as the next turn starts
+            let mut next = player.clone();
+            let old_dir = next.direction;
+            let round = next.round;
+            next.pre_step(&self.graph, &vec![], &ChoiceLocks::default());
+            self.map.update_lap(&mut next, old_dir);
+            return Outcome::Delayed(Box::new(if next.round > round {
+                Outcome::Finished
+            } else {
+                self.landing(&next)
+            }));
+        }
+
+        if player.outside_board() {
+            // Reversing back onto the board takes the next turn
+            let facings = player.reentry_facings(&self.map.tiles, None);
+            return Outcome::Delayed(Box::new(Outcome::Choice(
+                facings
+                    .into_iter()
+                    .map(|direction| self.index[&Solver::reentry_state(player.old_position, direction, player.first_half)])
+                    .collect(),
+            )));
+        }
+
+        let tile = &self.map.tiles[&player.position];
+        let state = |direction| SolverState {
+            position: player.position,
+            direction,
+            gear: player.gear,
+            stalled: player.stalled,
+            forced_gear_down: tile.blue,
+            first_half: player.first_half,
+        };
+
+        if player.rules.variant == Variant::Babyracerbile {
+            // Facing chosen before the next roll
+            let facings = player.start_facings(&self.map.tiles, None);
+            Outcome::Choice(facings.into_iter().map(|d| self.index[&state(d)]).collect())
+        } else if tile.rotate {
+            Outcome::Random((0..6).map(|d| self.index[&state(Direction::from_num(d))]).collect())
+        } else {
+            Outcome::State(self.index[&state(player.direction)])
+        }
+    }
+
+    fn outcome_value(&self, outcome: &Outcome) -> f64 {
+        match outcome {
+            Outcome::Finished => 0.0,
+            Outcome::State(s) => self.values[*s],
+            Outcome::Random(states) => {
+                states.iter().map(|s| self.values[*s]).sum::<f64>() / states.len() as f64
+            }
+            Outcome::Choice(states) => states.iter().map(|s| self.values[*s]).fold(f64::INFINITY, f64::min),
+            Outcome::Delayed(outcome) => 1.0 + self.outcome_value(outcome),
+        }
+    }
+
+    fn child_value(&self, child: &Child) -> f64 {
+        match child {
+            Child::Node(n) => self.node_values[*n],
+            Child::Outcome(o) => self.outcome_value(o),
+        }
+    }
+
+    /// Run value iteration until the largest change is below `epsilon`,
+    /// returns the final change
+    pub fn iterate(&mut self, epsilon: f64, max_iterations: usize) -> f64 {
+        let mut delta = f64::INFINITY;
+        while delta > epsilon && self.iterations < max_iterations {
+            // Nodes are in post order, so children are evaluated first
+            for n in 0..self.nodes.len() {
+                self.node_values[n] = self.nodes[n]
+                    .iter()
+                    .map(|(_, _, c)| self.child_value(c))
+                    .fold(f64::INFINITY, f64::min);
+            }
+
+            delta = 0.0;
+            for s in 0..self.states.len() {
+                let (change, value) = GEAR_CHANGES
+                    .iter()
+                    .zip(&self.roots[s])
+                    .map(|(change, rolls)| {
+                        let expected: f64 = rolls.iter().map(|(p, c)| p * self.child_value(c)).sum();
+                        (*change, 1.0 + expected)
+                    })
+                    .fold((ChangeGear::Stay, f64::INFINITY), |best, x| if x.1 < best.1 { x } else { best });
+
+                delta = delta.max((value - self.values[s]).abs());
+                self.values[s] = value;
+                self.gears[s] = change;
+            }
+            self.iterations += 1;
+        }
+        delta
+    }
+
+    pub fn state_of(player: &Player) -> SolverState {
+        SolverState {
+            position: player.position,
+            direction: player.direction,
+            gear: player.gear,
+            stalled: player.stalled,
+            forced_gear_down: player.forced_gear_down,
+            first_half: player.first_half,
+        }
+    }
+
+    // State after reversing back onto the board at `position`
+    fn reentry_state(position: Coord, direction: Direction, first_half: bool) -> SolverState {
+        SolverState {
+            position,
+            direction,
+            gear: 1,
+            stalled: false,
+            forced_gear_down: false,
+            first_half,
+        }
+    }
+
+    /// Expected number of turns to finish the lap from the state
+    pub fn value(&self, state: &SolverState) -> Option<f64> {
+        self.index.get(state).map(|s| self.values[*s])
+    }
+
+    pub fn best_gear(&self, state: &SolverState) -> Option<ChangeGear> {
+        self.index.get(state).map(|s| self.gears[*s])
+    }
+
+    pub fn best_turn(&self, key: &DecisionKey) -> Option<Turn> {
+        self.best_action(key).map(|(turn, _)| turn)
+    }
+
+    /// Stopping early is the best choice at the decision
+    pub fn best_stop(&self, key: &DecisionKey) -> bool {
+        self.best_action(key).is_some_and(|(_, stop)| stop)
+    }
+
+    // Turn and whether to stop after it, driving on with the first of equally good turns
+    fn best_action(&self, key: &DecisionKey) -> Option<(Turn, bool)> {
+        let node = self.decisions.get(key)?;
+        let (action, _) = self.nodes[*node]
+            .iter()
+            .map(|(turn, stop, c)| ((*turn, *stop), self.child_value(c)))
+            .fold(((Turn::Straight, false), f64::INFINITY), |best, x| if x.1 < best.1 { x } else { best });
+        Some(action)
+    }
+
+    /// Facing with the fewest expected turns after reversing back onto the board
+    /// or before rolling
+    pub fn best_facing(&self, player: &Player, facings: &[Direction]) -> Option<Direction> {
+        facings.iter().copied().min_by(|a, b| {
+            let state = |direction| if player.outside_board() {
+                Solver::reentry_state(player.old_position, direction, player.first_half)
+            } else {
+                SolverState { direction, ..Solver::state_of(player) }
+            };
+            let value = |d| self.value(&state(d)).unwrap_or(f64::INFINITY);
+            value(*a).total_cmp(&value(*b))
+        })
+    }
+
+    /// Expected lap time from the first grid placement, facing freely
+    pub fn lap_value(&self) -> f64 {
+        let mut player = self.map.player_builder.clone().next_player();
+        player.stalled = true;
+        player
+            .start_facings(&self.map.tiles, None)
+            .into_iter()
+            .map(|direction| self.value(&SolverState { direction, ..Solver::state_of(&player) }).unwrap_or(f64::INFINITY))
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    /// Expected turns and best gear change of every state, as CSV
+    pub fn gear_table(&self) -> String {
+        let mut table = String::from("q,r,direction,gear,stalled,forced_gear_down,first_half,expected_turns,gear_change\n");
+        for (s, state) in self.states.iter().enumerate() {
+            table += &format!(
+                "{},{},{:?},{},{},{},{},{:.4},{:?}\n",
+                state.position.q,
+                state.position.r,
+                state.direction,
+                state.gear,
+                state.stalled,
+                state.forced_gear_down,
+                state.first_half,
+                self.values[s],
+                self.gears[s],
+            );
+        }
+        table
+    }
+
+    /// Best turn at every decision inside a move, as CSV
+    pub fn turn_table(&self) -> String {
+        let mut table = String::from("q,r,old_q,old_r,direction,steps,roll,boost,gear,first_half,oriented,next_falls_off,turn,stop\n");
+        for key in self.decisions.keys() {
+            table += &format!(
+                "{},{},{},{},{:?},{},{},{},{},{},{},{},{:?},{}\n",
+                key.position.q,
+                key.position.r,
+                key.old_position.q,
+                key.old_position.r,
+                key.direction,
+                key.steps,
+                key.roll,
+                key.boost,
+                key.gear,
+                key.first_half,
+                key.oriented,
+                key.next_falls_off,
+                self.best_turn(key).unwrap(),
+                self.best_stop(key),
+            );
+        }
+        table
+    }
+}
+
+pub struct SolverGearStrategy<'a> {
+    pub solver: &'a Solver,
+}
+
+impl<'a> GearStrategy for SolverGearStrategy<'a> {
+    fn gear_strategy(&mut self, player: &Player) -> ChangeGear {
+        self.solver
+            .best_gear(&Solver::state_of(player))
+            .unwrap_or(ChangeGear::Stay)
+    }
+}
+
+pub struct SolverStepStrategy<'a> {
+    pub solver: &'a Solver,
+}
+
+impl<'a> StepStrategy for SolverStepStrategy<'a> {
+    fn step_strategy(&mut self, player: &Player, _: &Vec<Turn>, _: &Tile) -> Option<Turn> {
+        Some(
+            self.solver
+                .best_turn(&DecisionKey::from_player(player))
+                .unwrap_or(Turn::Straight),
+        )
+    }
+
+    fn stop_strategy(&mut self, player: &Player) -> bool {
+        self.solver.best_stop(&DecisionKey::from_player(player))
+    }
+
+    fn facing_strategy(&mut self, player: &Player, facings: &[Direction]) -> Option<Direction> {
+        self.solver.best_facing(player, facings)
+    }
+}
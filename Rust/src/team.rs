@@ -0,0 +1,195 @@
+use crate::map::*;
+use crate::player::*;
+use crate::rules::*;
+use crate::session::*;
+use std::fmt;
+use std::sync::Arc;
+
+// Le Mans team endurance races from `Python/rules.tex`: a team of drivers
+// shares one car and takes turns at the wheel. The sips of a turn go to the
+// driver at the wheel. Drivers swap for free in the pit lane, anywhere else
+// the incoming driver finishes a beer first. After a long session the teams
+// are classified by laps completed, then by how far into the lap they got.
+
+/// Turns a driver stays at the wheel before the team looks for the pit
+pub const STINT_TURNS: usize = 8;
+
+#[derive(Clone, Default, Debug)]
+pub struct Driver {
+    pub name: String,
+    pub sips: Sips,
+    pub waived: u32,  // Sips over the most one turn can cost
+    pub turns: usize, // Turns at the wheel
+}
+
+impl Driver {
+    pub fn total(&self) -> u32 {
+        self.sips.total() - self.waived
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Team {
+    pub name: String,
+    pub car: usize, // Index of the car in `GameState::players`
+    pub drivers: Vec<Driver>,
+    pub driver: usize, // At the wheel
+    pub stint: usize,  // Turns the driver at the wheel has had since the last swap
+    pub swaps: usize,
+}
+
+impl Team {
+    /// Team of player `entrant`, racing car `car`
+    pub fn new(car: usize, entrant: usize, drivers: usize) -> Self {
+        Team {
+            name: format!("Team {}", entrant + 1),
+            car,
+            drivers: (0..drivers)
+                .map(|d| Driver {
+                    name: format!("Driver {}.{}", entrant + 1, d + 1),
+                    ..Driver::default()
+                })
+                .collect(),
+            driver: 0,
+            stint: 0,
+            swaps: 0,
+        }
+    }
+
+    pub fn sips(&self) -> u32 {
+        self.drivers.iter().map(Driver::total).sum()
+    }
+}
+
+/// Teams of drivers on top of a headless game, one car each
+pub struct LeMansRace<'a> {
+    pub game: GameState<'a>,
+    pub teams: Vec<Team>,
+    pub rules: LeMans,
+    pub stint: usize,  // See `STINT_TURNS`
+    booked: Vec<Sips>, // Sips of each car already on a driver's ledger
+}
+
+impl<'a> LeMansRace<'a> {
+    /// Race on `map`, turning on the Le Mans rules if the rule set of the board lacks them
+    pub fn new(mut map: HexMap, strategies: Vec<(PlayerGearStrategy, PlayerStepStrategy)>, seed: u64) -> Self {
+        if map.player_builder.rules.le_mans.is_none() {
+            let mut rules = (*map.player_builder.rules).clone();
+            rules.le_mans = Some(LeMans::default());
+            map.player_builder.rules = Arc::new(rules);
+        }
+        let rules = map.player_builder.rules.le_mans.clone().unwrap();
+        let teams = (0..map.player_builder.total)
+            .map(|car| Team::new(car, map.player_builder.entrant(car), rules.drivers))
+            .collect();
+
+        let game = GameState::headless(map, strategies, seed);
+        LeMansRace {
+            teams,
+            booked: vec![Sips::default(); game.players.len()],
+            stint: STINT_TURNS,
+            rules,
+            game,
+        }
+    }
+
+    /// Play until the session on `clock` is over
+    pub fn run(&mut self, clock: SessionClock) {
+        self.game.start_session(clock);
+        while !self.game.session.over {
+            self.step();
+        }
+    }
+
+    /// Advance the game by one step, swapping drivers before a turn and
+    /// booking the sips on the driver at the wheel after it
+    pub fn step(&mut self) -> bool {
+        let car = self.game.player_index;
+        let rolling = self.game.rolling;
+        if rolling {
+            self.change_drivers(car);
+        }
+
+        let stepped = self.game.step_game(None);
+
+        if rolling && !self.game.rolling {
+            // The turn started
+            let team = &mut self.teams[car];
+            team.stint += 1;
+            team.drivers[team.driver].turns += 1;
+        }
+        if !rolling && self.game.rolling {
+            self.book(car);
+        }
+        stepped
+    }
+
+    /// A driver whose stint is over is swapped in the pit lane,
+    /// or anywhere once the stint has run twice as long
+    fn change_drivers(&mut self, car: usize) {
+        let team = &self.teams[car];
+        if team.drivers.len() < 2 || team.stint < self.stint {
+            return;
+        }
+        let player = &self.game.players[car];
+        let in_pit = !player.outside_board() && self.game.map.tiles.get(&player.position).is_some_and(|t| t.is_pit());
+        if !in_pit && team.stint < 2 * self.stint {
+            return;
+        }
+
+        let team = &mut self.teams[car];
+        team.driver = (team.driver + 1) % team.drivers.len();
+        team.stint = 0;
+        team.swaps += 1;
+        if !in_pit {
+            team.drivers[team.driver].sips.beers += self.rules.swap_beers;
+        }
+    }
+
+    fn book(&mut self, car: usize) {
+        let sips = self.game.players[car].sips.since(&self.booked[car]);
+        self.booked[car] = self.game.players[car].sips.clone();
+
+        let team = &mut self.teams[car];
+        let driver = &mut team.drivers[team.driver];
+        driver.waived += sips.total().saturating_sub(self.rules.max_sips_per_turn);
+        driver.sips.add(&sips);
+    }
+
+    /// Teams by laps completed, then by track position
+    pub fn classification(&self) -> Vec<&Team> {
+        self.game
+            .classification()
+            .into_iter()
+            .map(|car| self.teams.iter().find(|t| t.car == car).unwrap())
+            .collect()
+    }
+}
+
+impl fmt::Display for LeMansRace<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (place, team) in self.classification().iter().enumerate() {
+            let (laps, _, _) = self.game.standing(team.car);
+            writeln!(
+                f,
+                "{}. {:<8} {} laps, {} sips, {} swaps",
+                place + 1,
+                team.name,
+                laps,
+                team.sips(),
+                team.swaps
+            )?;
+            for driver in &team.drivers {
+                writeln!(
+                    f,
+                    "   {:<12} {:>3} turns {:>4} sips {:>2} beers",
+                    driver.name,
+                    driver.turns,
+                    driver.total(),
+                    driver.sips.beers
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
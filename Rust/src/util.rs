@@ -1,13 +1,34 @@
 use crate::canvas_draw::*;
+use crate::effects::*;
 use sdl2::pixels::Color;
-use sdl2::rect::Point;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
+use std::cell::Cell;
 use std::cmp::{max, min};
 use std::collections::BTreeMap;
 use std::f64::consts::PI;
 use std::ops::{Add, Mul, Sub};
 
+thread_local! {
+    // Debug output of the engine, turned off for batch simulations and solvers
+    static VERBOSE: Cell<bool> = const { Cell::new(true) };
+}
+
+pub fn verbose() -> bool {
+    VERBOSE.with(|v| v.get())
+}
+
+pub fn set_verbose(value: bool) {
+    VERBOSE.with(|v| v.set(value));
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if $crate::util::verbose() {
+            println!($($arg)*);
+        }
+    };
+}
+
 #[derive(Copy, Clone, PartialOrd, Ord, Eq, PartialEq, Debug)]
 pub enum Direction {
     UR,
@@ -142,6 +163,7 @@ pub struct Tile {
     pub oneway: bool,
     pub blockage: Vec<Direction>,
     pub forced: BTreeMap<Coord, Direction>,
+    pub effects: Vec<TileEffect>, // Besides blue and rotate tiles
 }
 
 impl Default for Tile {
@@ -156,6 +178,7 @@ impl Default for Tile {
             oneway: false,
             blockage: vec![],
             forced: BTreeMap::new(),
+            effects: vec![],
         }
     }
 }
@@ -278,6 +301,29 @@ impl Tile {
         Tile { blue: true, ..self }
     }
 
+    pub fn pit(self) -> Self {
+        self.effect(TileEffect::PitLane)
+    }
+
+    pub fn effect(mut self, effect: TileEffect) -> Self {
+        self.effects.push(effect);
+        self
+    }
+
+    pub fn is_pit(&self) -> bool {
+        self.effects.contains(&TileEffect::PitLane)
+    }
+
+    /// Effects of the tile, blue and rotate tiles included
+    pub fn all_effects(&self) -> Vec<TileEffect> {
+        let flags = [(self.blue, TileEffect::GearDown), (self.rotate, TileEffect::Rotate)];
+        flags
+            .into_iter()
+            .filter_map(|(on, effect)| on.then_some(effect))
+            .chain(self.effects.iter().cloned())
+            .collect()
+    }
+
     pub fn chikane(self) -> Self {
         Tile {
             chikane: true,
@@ -317,10 +363,18 @@ impl Tile {
         Tile { forced, ..self }
     }
 
-    pub fn draw(&self, canvas: &mut Canvas<Window>, c: Coord, scale: f64) {
+    pub fn draw(&self, canvas: &mut impl Painter, c: Coord, scale: f64) {
         let intensity = if self.start_field { 120 } else { 200 };
         let fill_color = if self.blue {
             Color::RGB(0, 0, intensity)
+        } else if self.is_pit() {
+            Color::RGB(intensity - 60, intensity - 60, intensity - 60)
+        } else if self.effects.contains(&TileEffect::FortyTwo) {
+            Color::RGB(intensity, 0, intensity)
+        } else if self.effects.iter().any(|e| matches!(e, TileEffect::Boost(_))) {
+            Color::RGB(0, intensity, 0)
+        } else if self.effects.contains(&TileEffect::OilSlick) {
+            Color::RGB(intensity / 4, intensity / 4, intensity / 4)
         } else {
             Color::RGB(intensity, intensity, 0)
         };
@@ -372,12 +426,12 @@ impl Tile {
                 }
             }
         } else {
-            fill_hexagon(canvas, c.x(), c.y(), scale);
+            canvas.fill_hexagon(c.x(), c.y(), scale);
         }
 
         if self.chikane {
             canvas.set_draw_color(Color::RGB(200, 0, 0));
-            fill_hexagon(canvas, c.x(), c.y(), scale / 2.0);
+            canvas.fill_hexagon(c.x(), c.y(), scale / 2.0);
         }
 
         if self.rotate {